@@ -1,55 +1,263 @@
 import { FormGroup } from "../forms/FormGroup";
+import { SelectInput } from "../forms/SelectInput";
 import { MatrixInput } from "../forms/MatrixInput";
 import { NumberInput } from "../forms/NumberInput";
 import { MiniExpandable } from "../layout/MiniExpandable";
 import { useDays, useMitigation, useParams } from "../ModelState";
-import { CommunityMitigationParamsExport } from "@wasm/wasm_dynode";
+import {
+    CommunityMitigationParamsExport,
+    CommunityMitigationPeriodExport,
+    MitigationRelease,
+    MitigationTrigger,
+} from "@wasm/wasm_dynode";
+
+type Option<T> = { value: T; label: string };
+
+let triggerOptions: Option<MitigationTrigger>[] = [
+    { value: "Day", label: "On a given day" },
+    {
+        value: { HospitalAdmissions: 5 },
+        label: "When hospital admissions per 100k reach",
+    },
+    { value: { Detection: 0.9 }, label: "When the epidemic is detected" },
+];
+
+let releaseOptions: Option<MitigationRelease>[] = [
+    { value: "Duration", label: "After a given duration" },
+    {
+        value: { HospitalAdmissions: 2 },
+        label: "When hospital admissions per 100k fall below",
+    },
+];
+
+// Options are told apart by their variant, whatever their threshold
+let variant = (value: object | string) =>
+    typeof value === "string" ? value : Object.keys(value)[0];
 
 export function CommunityEditor() {
-    let [{ start, duration, effectiveness }, updateParams] =
+    let [{ periods }, updateParams] =
         useMitigation<CommunityMitigationParamsExport>("community");
     let [params] = useParams();
     let [days] = useDays();
+
+    let updatePeriod = (
+        index: number,
+        update: Partial<CommunityMitigationPeriodExport>
+    ) => {
+        updateParams({
+            periods: periods.map((period, i) =>
+                i === index ? { ...period, ...update } : period
+            ),
+        });
+    };
+
     return (
         <div>
-            <FormGroup>
-                <label>Day to begin community mitigation</label>
-                <NumberInput
-                    range
-                    min={0}
-                    max={days}
-                    step={1}
-                    value={start}
-                    onValue={(start) => updateParams({ start })}
-                />
-            </FormGroup>
-            <FormGroup>
-                <label>Duration of community mitigation</label>
-                <NumberInput
-                    range
-                    min={1}
-                    max={days - start}
-                    value={duration}
-                    onValue={(duration) => updateParams({ duration })}
-                />
-            </FormGroup>
-            <FormGroup>
-                <MiniExpandable
-                    title="Community mitigation effectiveness"
-                    initialState={true}
-                >
-                    <MatrixInput
-                        value={effectiveness}
-                        step={0.1}
-                        min={0.0}
-                        max={1.0}
-                        symmetric={params.population_fraction_labels}
-                        onChange={(newVal) => {
-                            updateParams({ effectiveness: newVal });
-                        }}
-                    />
-                </MiniExpandable>
-            </FormGroup>
+            {periods.map(
+                (
+                    {
+                        start,
+                        duration,
+                        effectiveness,
+                        setting_effectiveness,
+                        trigger,
+                        release,
+                    },
+                    index
+                ) => (
+                    <div key={index} className="mb-2">
+                        <FormGroup>
+                            <label>Start community mitigation</label>
+                            <SelectInput
+                                value={triggerOptions.find(
+                                    (o) => variant(o.value) === variant(trigger),
+                                )}
+                                options={triggerOptions}
+                                onChange={(option: unknown) => {
+                                    let trigger = (
+                                        option as Option<MitigationTrigger> | null
+                                    )?.value;
+                                    if (trigger) {
+                                        updatePeriod(index, { trigger });
+                                    }
+                                }}
+                            />
+                        </FormGroup>
+                        {typeof trigger !== "string" && (
+                            <FormGroup>
+                                <label>
+                                    {"HospitalAdmissions" in trigger
+                                        ? "Daily admissions per 100k"
+                                        : "Probability of detection"}
+                                </label>
+                                <NumberInput
+                                    min={0}
+                                    max={"Detection" in trigger ? 1 : undefined}
+                                    step={"Detection" in trigger ? 0.05 : 1}
+                                    numberType="float"
+                                    value={Object.values(trigger)[0]}
+                                    onValue={(threshold) =>
+                                        updatePeriod(index, {
+                                            trigger: {
+                                                [variant(trigger)]: threshold,
+                                            } as MitigationTrigger,
+                                        })
+                                    }
+                                />
+                            </FormGroup>
+                        )}
+                        <FormGroup>
+                            <label>
+                                {trigger === "Day"
+                                    ? "Day to begin community mitigation"
+                                    : "Earliest day to begin community mitigation"}
+                            </label>
+                            <NumberInput
+                                range
+                                min={0}
+                                max={days}
+                                step={1}
+                                value={start}
+                                onValue={(start) => updatePeriod(index, { start })}
+                            />
+                        </FormGroup>
+                        <FormGroup>
+                            <label>Lift community mitigation</label>
+                            <SelectInput
+                                value={releaseOptions.find(
+                                    (o) => variant(o.value) === variant(release),
+                                )}
+                                options={releaseOptions}
+                                onChange={(option: unknown) => {
+                                    let release = (
+                                        option as Option<MitigationRelease> | null
+                                    )?.value;
+                                    if (release) {
+                                        updatePeriod(index, { release });
+                                    }
+                                }}
+                            />
+                        </FormGroup>
+                        {release === "Duration" ? (
+                            <FormGroup>
+                                <label>Duration of community mitigation</label>
+                                <NumberInput
+                                    range
+                                    min={1}
+                                    max={days - start}
+                                    value={duration}
+                                    onValue={(duration) =>
+                                        updatePeriod(index, { duration })
+                                    }
+                                />
+                            </FormGroup>
+                        ) : (
+                            <FormGroup>
+                                <label>Daily admissions per 100k</label>
+                                <NumberInput
+                                    min={0}
+                                    step={1}
+                                    numberType="float"
+                                    value={release.HospitalAdmissions}
+                                    onValue={(threshold) =>
+                                        updatePeriod(index, {
+                                            release: {
+                                                HospitalAdmissions: threshold,
+                                            },
+                                        })
+                                    }
+                                />
+                            </FormGroup>
+                        )}
+                        {params.setting_contacts.map(({ setting }) => (
+                            <FormGroup key={setting}>
+                                <label>
+                                    Reduction in {setting.toLowerCase()} contacts
+                                </label>
+                                <NumberInput
+                                    range
+                                    min={0}
+                                    max={1}
+                                    step={0.05}
+                                    numberType="float"
+                                    value={
+                                        setting_effectiveness.find(
+                                            (s) => s.setting === setting
+                                        )?.effectiveness ?? 0
+                                    }
+                                    onValue={(effectiveness) =>
+                                        updatePeriod(index, {
+                                            setting_effectiveness: [
+                                                ...setting_effectiveness.filter(
+                                                    (s) => s.setting !== setting
+                                                ),
+                                                { setting, effectiveness },
+                                            ],
+                                        })
+                                    }
+                                />
+                            </FormGroup>
+                        ))}
+                        <FormGroup>
+                            <MiniExpandable
+                                title="Community mitigation effectiveness"
+                                initialState={index === 0}
+                            >
+                                <MatrixInput
+                                    value={effectiveness}
+                                    step={0.1}
+                                    min={0.0}
+                                    max={1.0}
+                                    symmetric={params.population_fraction_labels}
+                                    onChange={(newVal) => {
+                                        updatePeriod(index, {
+                                            effectiveness: newVal,
+                                        });
+                                    }}
+                                />
+                            </MiniExpandable>
+                        </FormGroup>
+                        {periods.length > 1 && (
+                            <button
+                                onClick={() =>
+                                    updateParams({
+                                        periods: periods.filter(
+                                            (_, i) => i !== index
+                                        ),
+                                    })
+                                }
+                            >
+                                Remove period
+                            </button>
+                        )}
+                    </div>
+                ),
+            )}
+            <button
+                onClick={() => {
+                    let last = periods[periods.length - 1];
+                    updateParams({
+                        periods: [
+                            ...periods,
+                            {
+                                start: Math.min(
+                                    last.start + last.duration,
+                                    days - 1
+                                ),
+                                duration: last.duration,
+                                effectiveness: [...last.effectiveness],
+                                setting_effectiveness: [
+                                    ...last.setting_effectiveness,
+                                ],
+                                trigger: last.trigger,
+                                release: last.release,
+                            },
+                        ],
+                    });
+                }}
+            >
+                Add period
+            </button>
         </div>
     );
 }
@@ -2,7 +2,11 @@ import { FormGroup } from "../forms/FormGroup";
 import { SelectInput } from "../forms/SelectInput";
 import { NumberInput } from "../forms/NumberInput";
 import { useDays, useMitigation, useParams } from "../ModelState";
-import { VaccineParams } from "@wasm/wasm_dynode";
+import {
+    VaccineAllocation,
+    VaccineParams,
+    VaccineTargeting,
+} from "@wasm/wasm_dynode";
 
 export function VaccineEditor() {
     let [modelParams] = useParams();
@@ -12,6 +16,17 @@ export function VaccineEditor() {
         { value: 1, label: "One dose" },
         { value: 2, label: "Two doses" },
     ];
+    let targetingOptions: { value: VaccineTargeting; label: string }[] = [
+        { value: "All", label: "Everyone" },
+        { value: "Seronegative", label: "Seronegative people" },
+        { value: "Susceptible", label: "Susceptible people only" },
+    ];
+    let groups = Array.from({ length: modelParams.n }, (_, g) => g);
+    let allocationOptions: { value: VaccineAllocation; label: string }[] = [
+        { value: "ProRata", label: "In proportion to group size" },
+        { value: { Priority: groups }, label: "Youngest first" },
+        { value: { Priority: [...groups].reverse() }, label: "Oldest first" },
+    ];
     return (
         <div>
             <FormGroup>
@@ -24,11 +39,48 @@ export function VaccineEditor() {
                             option as { value: number; label: string } | null
                         )?.value;
                         if (doses) {
-                            updateParams({ doses });
+                            // One efficacy for each dose before the last
+                            let resize = (ve: number[]) =>
+                                Array.from(
+                                    { length: doses - 1 },
+                                    (_, k) => ve[k] ?? 0,
+                                );
+                            updateParams({
+                                doses,
+                                partial_ve_s: resize(params.partial_ve_s),
+                                partial_ve_i: resize(params.partial_ve_i),
+                                partial_ve_p: resize(params.partial_ve_p),
+                            });
                         }
                     }}
                 />
             </FormGroup>
+            {params.doses > 1 && (
+                <FormGroup>
+                    <label>Days between doses</label>
+                    <NumberInput
+                        range
+                        min={1}
+                        max={90}
+                        value={params.dose_interval}
+                        onValue={(dose_interval) =>
+                            updateParams({ dose_interval })
+                        }
+                    />
+                </FormGroup>
+            )}
+            <FormGroup>
+                <label>Days from vaccination to protection</label>
+                <NumberInput
+                    range
+                    min={0}
+                    max={30}
+                    value={params.ramp_up_period}
+                    onValue={(ramp_up_period) =>
+                        updateParams({ ramp_up_period })
+                    }
+                />
+            </FormGroup>
             <FormGroup>
                 <label>Vaccination start</label>
                 <NumberInput
@@ -39,6 +91,48 @@ export function VaccineEditor() {
                     onValue={(start) => updateParams({ start })}
                 />
             </FormGroup>
+            <FormGroup>
+                <label>Vaccinate</label>
+                <SelectInput
+                    value={targetingOptions.find(
+                        (o) => o.value === params.targeting,
+                    )}
+                    options={targetingOptions}
+                    onChange={(option: unknown) => {
+                        let targeting = (
+                            option as {
+                                value: VaccineTargeting;
+                                label: string;
+                            } | null
+                        )?.value;
+                        if (targeting) {
+                            updateParams({ targeting });
+                        }
+                    }}
+                />
+            </FormGroup>
+            <FormGroup>
+                <label>Vaccine allocation</label>
+                <SelectInput
+                    value={allocationOptions.find(
+                        (o) =>
+                            JSON.stringify(o.value) ===
+                            JSON.stringify(params.allocation),
+                    )}
+                    options={allocationOptions}
+                    onChange={(option: unknown) => {
+                        let allocation = (
+                            option as {
+                                value: VaccineAllocation;
+                                label: string;
+                            } | null
+                        )?.value;
+                        if (allocation) {
+                            updateParams({ allocation });
+                        }
+                    }}
+                />
+            </FormGroup>
             <FormGroup>
                 <label>Vaccine doses available</label>
                 <NumberInput
@@ -95,6 +189,27 @@ export function VaccineEditor() {
                     onValue={(ve_p) => updateParams({ ve_p: ve_p / 100 })}
                 />
             </FormGroup>
+            {params.partial_ve_s.map((_, dose) => (
+                <FormGroup key={dose}>
+                    <label>
+                        Vaccine effectiveness against infection after dose{" "}
+                        {dose + 1}
+                    </label>
+                    <NumberInput
+                        range
+                        min={0}
+                        max={100}
+                        value={params.partial_ve_s[dose] * 100}
+                        onValue={(ve_s) =>
+                            updateParams({
+                                partial_ve_s: params.partial_ve_s.map((ve, k) =>
+                                    k === dose ? ve_s / 100 : ve,
+                                ),
+                            })
+                        }
+                    />
+                </FormGroup>
+            ))}
         </div>
     );
 }
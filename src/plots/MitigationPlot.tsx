@@ -1,7 +1,11 @@
 import * as Plot from "@observablehq/plot";
 import { PointPlot, PointPlotProps } from "./PointPlot";
 import { useModelRunData } from "../state/modelRuns";
-import { MitigationType, Parameters } from "@wasm/wasm_dynode";
+import {
+    CommunityMitigationWindow,
+    MitigationType,
+    Parameters,
+} from "@wasm/wasm_dynode";
 import { DataByGroupMap, dodge, getPeakY, ValidGroupKey } from "./plotUtils";
 import { useParams } from "../ModelState";
 import { Point } from "../state/modelRuns";
@@ -20,7 +24,7 @@ export function MitigationPlot<F extends ValidGroupKey<Point> = never>({
 }: Partial<PointPlotProps<Point, "mitigation_type", F>> & {
     annotations?: boolean;
 }) {
-    let { dt } = useModelRunData();
+    let { dt, community_mitigations } = useModelRunData();
     let [params] = useParams();
     if (!dt) {
         return null;
@@ -49,7 +53,18 @@ export function MitigationPlot<F extends ValidGroupKey<Point> = never>({
             renderMarks={(dataByGroup, _, { xScale, yScale }) => {
                 let mitigated = dataByGroup.get("Mitigated");
                 let unmitigated = dataByGroup.get("Unmitigated");
-                let marks: Plot.Markish[] = [];
+                // Spread of stochastic runs around their median
+                let marks: Plot.Markish[] = [...dataByGroup.values()]
+                    .filter((points) => points.some((p) => p.upper > p.lower))
+                    .map((points) =>
+                        Plot.areaY(points, {
+                            x: "x",
+                            y1: "lower",
+                            y2: "upper",
+                            fill: "mitigation_type",
+                            fillOpacity: 0.15,
+                        })
+                    );
 
                 if (!unmitigated && !unmitigated) return marks;
 
@@ -90,6 +105,8 @@ export function MitigationPlot<F extends ValidGroupKey<Point> = never>({
                 let peakLabels = getPeakLabels(dataByGroup, xScale, yScale);
                 let { annotations, annotationSegments } = getAnnotations({
                     params,
+                    communityWindows:
+                        community_mitigations?.get("Mitigated") || [],
                     dataByGroup,
                     xScale,
                 });
@@ -228,10 +245,12 @@ interface Annotation {
 
 function getAnnotations({
     params,
+    communityWindows,
     dataByGroup,
     xScale,
 }: {
     params: Parameters;
+    communityWindows: CommunityMitigationWindow[];
     dataByGroup: DataByGroupMap<Point, "mitigation_type">;
     xScale: Plot.Scale;
 }): {
@@ -290,16 +309,21 @@ function getAnnotations({
     }
 
     if (community.enabled) {
-        // TODO off by 1?
-        const startX = community.start + 1;
-        const endX = startX + community.duration;
-        tryAddAnnotation(
-            startX,
-            endX,
-            `Community mitigation`,
-            "var(--pink)",
-            "community"
-        );
+        // Periods that were put in effect during the run, including those
+        // triggered by the state of the epidemic
+        communityWindows.forEach(({ start, end }) => {
+            // TODO off by 1?
+            const startX = start + 1;
+            const endX =
+                end == null ? mitigatedData[mitigatedData.length - 1].x : end + 1;
+            tryAddAnnotation(
+                startX,
+                endX,
+                `Community mitigation`,
+                "var(--pink)",
+                "community"
+            );
+        });
     }
 
     annotations.sort((a, b) => a.startX - b.startX);
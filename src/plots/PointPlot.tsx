@@ -167,9 +167,33 @@ export function PointPlotInner<
     let widthHeight = useResize(plotRef);
 
     let [dataByGroup, dataByX, maxX, maxY] = useMemo(() => {
+        // Bands of stochastic runs do not add up over groups like the
+        // values, as quantiles of a sum are not sums of quantiles, so the
+        // band of the total is used when several groups are summed
+        let columns = dataTable.columnNames();
+        let hasBands = columns.includes("upper");
+        let hasTotalBands = columns.includes("total_upper");
         let dt = dataTable.groupby(groupBy, "x").rollup({
             y: op.sum("y"),
+            ...(hasBands
+                ? { lower: op.sum("lower"), upper: op.sum("upper") }
+                : {}),
+            ...(hasTotalBands
+                ? {
+                      total_lower: op.max("total_lower"),
+                      total_upper: op.max("total_upper"),
+                      rows: op.count(),
+                  }
+                : {}),
         });
+        if (hasBands && hasTotalBands) {
+            dt = dt.derive({
+                // @ts-expect-error d is untyped
+                lower: (d) => (d.rows > 1 ? d.total_lower : d.lower),
+                // @ts-expect-error d is untyped
+                upper: (d) => (d.rows > 1 ? d.total_upper : d.upper),
+            });
+        }
 
         let [maxX, maxY] = calcMaxXY(userMaxX, userMaxY, dt);
 
@@ -324,7 +348,9 @@ function calcMaxXY(
     }
     const max = dataTable.rollup({
         maxX: op.max("x"),
-        maxY: op.max("y"),
+        maxY: op.max(
+            dataTable.columnNames().includes("upper") ? "upper" : "y"
+        ),
     });
 
     return [maxX ?? max.get("maxX") ?? 0, maxY ?? max.get("maxY") ?? 0];
@@ -0,0 +1,54 @@
+import * as Plot from "@observablehq/plot";
+import { useMemo } from "react";
+import { table } from "arquero";
+import { MitigationType } from "@wasm/wasm_dynode";
+import { PointPlot } from "./PointPlot";
+import { useModelRunData } from "../state/modelRuns";
+
+type RtPoint = {
+    x: number;
+    y: number;
+    mitigation_type: MitigationType;
+};
+
+// Effective reproduction number over time, with the epidemic threshold at 1
+export function RtPlot() {
+    let { rt } = useModelRunData();
+    let dataTable = useMemo(() => {
+        if (!rt) return null;
+        let points = [...rt.entries()].flatMap(([mitigation_type, items]) =>
+            items.map(({ x, y }) => ({ x, y, mitigation_type }))
+        );
+        return table({
+            x: points.map((p) => p.x),
+            y: points.map((p) => p.y),
+            mitigation_type: points.map((p) => p.mitigation_type),
+        });
+    }, [rt]);
+    if (!dataTable) {
+        return null;
+    }
+    return (
+        <PointPlot<RtPoint, "mitigation_type">
+            dataTable={dataTable}
+            yLabel="Rt"
+            groupBy="mitigation_type"
+            colors={
+                new Map<MitigationType, string>([
+                    ["Unmitigated", "#999"],
+                    ["Mitigated", "#000"],
+                ])
+            }
+            renderMarks={(dataByGroup) => [
+                Plot.ruleY([1], { stroke: "var(--red)", strokeDasharray: "4, 4" }),
+                ...[...dataByGroup.values()].map((points) =>
+                    Plot.line(points, {
+                        x: "x",
+                        y: "y",
+                        stroke: "mitigation_type",
+                    })
+                ),
+            ]}
+        />
+    );
+}
@@ -1,4 +1,5 @@
 import {
+    CommunityMitigationWindow,
     MitigationType,
     ModelOutputExport,
     OutputType,
@@ -14,11 +15,20 @@ export type ModelRunTable = {
     mitigation_types: MitigationType[];
     output_types: OutputType[];
     p_detect: Map<MitigationType, BasePoint[]>;
+    vaccine_stockpile: Map<MitigationType, BasePoint[]>;
+    rt: Map<MitigationType, BasePoint[]>;
+    community_mitigations: Map<MitigationType, CommunityMitigationWindow[]>;
 };
 
 export type Point = {
     x: number;
     y: number;
+    // quantile band over stochastic runs, equal to y for a single run
+    lower: number;
+    upper: number;
+    // quantile band of the total over all groups, for plots that sum them
+    total_lower: number;
+    total_upper: number;
     mitigation_type: MitigationType;
     output_type: OutputType;
     age_group: number;
@@ -43,6 +53,10 @@ export function buildModelRunTable(exported: ModelOutputExport): ModelRunTable {
         rows: {
             x: [],
             y: [],
+            lower: [],
+            upper: [],
+            total_lower: [],
+            total_upper: [],
             age_group: [],
             output_type: [],
             mitigation_type: [],
@@ -50,6 +64,11 @@ export function buildModelRunTable(exported: ModelOutputExport): ModelRunTable {
         mitigation_types: exported.mitigation_types,
         output_types: exported.output_types,
         p_detect: new Map<MitigationType, BasePoint[]>(),
+        vaccine_stockpile: new Map<MitigationType, BasePoint[]>(),
+        rt: new Map<MitigationType, BasePoint[]>(),
+        community_mitigations: new Map(
+            entries(exported.community_mitigations)
+        ),
     };
     entries(exported.p_detect)
         .sort()
@@ -63,16 +82,57 @@ export function buildModelRunTable(exported: ModelOutputExport): ModelRunTable {
             );
         });
 
+    entries(exported.vaccine_stockpile)
+        .sort()
+        .forEach(([mitigation_type, items]) => {
+            table.vaccine_stockpile.set(
+                mitigation_type,
+                items.map((item) => ({
+                    x: item.time,
+                    y: item.value,
+                }))
+            );
+        });
+
+    entries(exported.rt)
+        .sort()
+        .forEach(([mitigation_type, items]) => {
+            table.rt.set(
+                mitigation_type,
+                items.map((item) => ({
+                    x: item.time,
+                    y: item.value,
+                }))
+            );
+        });
+
     entries(exported.output)
         .sort()
         .forEach(([mitigation_type, output]) => {
             entries(output)
                 .sort()
                 .forEach(([output_type, items]) => {
-                    items.forEach((item) => {
+                    let bands =
+                        exported.bands[mitigation_type]?.[output_type] ?? [];
+                    let totalBands =
+                        exported.total_bands[mitigation_type]?.[output_type] ??
+                        [];
+                    items.forEach((item, day) => {
+                        let total = item.grouped_values.reduce(
+                            (sum, val) => sum + val,
+                            0
+                        );
                         item.grouped_values.forEach((val, i) => {
                             table.rows.x.push(item.time);
                             table.rows.y.push(val);
+                            table.rows.lower.push(bands[day]?.lower[i] ?? val);
+                            table.rows.upper.push(bands[day]?.upper[i] ?? val);
+                            table.rows.total_lower.push(
+                                totalBands[day]?.lower ?? total
+                            );
+                            table.rows.total_upper.push(
+                                totalBands[day]?.upper ?? total
+                            );
                             table.rows.age_group.push(i);
                             table.rows.output_type.push(output_type);
                             table.rows.mitigation_type.push(mitigation_type);
@@ -94,5 +154,8 @@ export function useModelRunData() {
         mitigation_types: modelRunTable?.mitigation_types || null,
         output_types: modelRunTable?.output_types || null,
         p_detect: modelRunTable?.p_detect || null,
+        vaccine_stockpile: modelRunTable?.vaccine_stockpile || null,
+        rt: modelRunTable?.rt || null,
+        community_mitigations: modelRunTable?.community_mitigations || null,
     };
 }
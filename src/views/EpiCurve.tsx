@@ -1,5 +1,6 @@
 import "./EpiCurve.css";
 import { MitigationPlot } from "../plots/MitigationPlot";
+import { RtPlot } from "../plots/RtPlot";
 import { useParams } from "../ModelState";
 import { useModelRunData } from "../state/modelRuns";
 import { match } from "../utils";
@@ -28,7 +29,13 @@ export function EpiCurve() {
                 <MitigationPlot
                     yLabel="Incidence"
                     facetBy="output_type"
-                    filter={(d) => d.output_type !== "InfectionIncidence"}
+                    filter={(d) =>
+                        [
+                            "SymptomaticIncidence",
+                            "HospitalIncidence",
+                            "DeathIncidence",
+                        ].includes(d.output_type)
+                    }
                     facetLabel={(outputType) =>
                         match(outputType, [
                             ["HospitalIncidence", () => "Hospitalizations"],
@@ -41,6 +48,50 @@ export function EpiCurve() {
                         ])
                     }
                 />
+                <MitigationPlot
+                    yLabel="Occupied beds"
+                    facetBy="output_type"
+                    filter={(d) =>
+                        ["HospitalCensus", "IcuCensus"].includes(
+                            d.output_type
+                        )
+                    }
+                    facetLabel={(outputType) =>
+                        match(outputType, [
+                            ["HospitalCensus", () => "Hospital Census"],
+                            ["IcuCensus", () => "ICU Census"],
+                        ])
+                    }
+                />
+            </section>
+            {params.variant && (
+                <section className="mb-3">
+                    <h3 className="mb-1">Infection Incidence by Strain</h3>
+                    <MitigationPlot
+                        yLabel="Incidence"
+                        facetBy="output_type"
+                        filter={(d) =>
+                            [
+                                "FirstStrainInfectionIncidence",
+                                "VariantInfectionIncidence",
+                            ].includes(d.output_type)
+                        }
+                        facetLabel={(outputType) =>
+                            match(outputType, [
+                                [
+                                    "FirstStrainInfectionIncidence",
+                                    () => "First Strain",
+                                ],
+                                ["VariantInfectionIncidence", () => "Variant"],
+                            ])
+                        }
+                        singleYAxis
+                    />
+                </section>
+            )}
+            <section className="mb-3">
+                <h3 className="mb-1">Effective Reproduction Number</h3>
+                <RtPlot />
             </section>
             <section className="mb-3">
                 <h3 className="mb-1">Infection Incidence by Age Group</h3>
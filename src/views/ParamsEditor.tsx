@@ -1,7 +1,13 @@
+import { useMemo, useState } from "react";
+import {
+    get_doubling_time,
+    r0_from_doubling_time,
+} from "@wasm/wasm_dynode";
 import { NumberInput } from "../forms/NumberInput";
+import { SelectInput } from "../forms/SelectInput";
 import { Mitigations } from "../mitigations/Mitigations";
 import { FormGroup } from "../forms/FormGroup";
-import { useDays, useParams } from "../ModelState";
+import { useDays, useParams, useParamsContext } from "../ModelState";
 import { GroupEditor } from "./GroupEditor";
 
 // let ag = () => [
@@ -12,10 +18,46 @@ import { GroupEditor } from "./GroupEditor";
 //     { value: "80+", label: "80+" },
 // ];
 
+let transmissionInputs = [
+    { value: "r0", label: "R0" },
+    { value: "doubling_time", label: "Doubling time" },
+] as const;
+
 export function ParamsEditor() {
     let [days, setDays] = useDays();
 
     let [params, updateParams] = useParams();
+    let { model } = useParamsContext();
+    // Each call builds the model again, and introductions iterate to a fixed
+    // point, so only on changes to the model
+    let reproductionNumbers = useMemo(
+        () => model?.reproduction_numbers(),
+        [model]
+    );
+    let introductions = useMemo(() => model?.introductions(), [model]);
+    let stochastic = params.stochastic;
+    let variant = params.variant;
+    let importations = params.importations;
+    let updateImportation = (
+        index: number,
+        update: Partial<(typeof importations)[number]>
+    ) =>
+        updateParams({
+            importations: importations.map((importation, i) =>
+                i === index ? { ...importation, ...update } : importation
+            ),
+        });
+    // Early in an outbreak, the doubling time is known rather than R0
+    let [transmissionInput, setTransmissionInput] = useState(
+        transmissionInputs[0]
+    );
+    // The conversions throw while an edit leaves the parameters invalid
+    let doublingTime: number | null;
+    try {
+        doublingTime = get_doubling_time(params);
+    } catch {
+        doublingTime = null;
+    }
 
     return (
         <div className="p-1">
@@ -52,19 +94,393 @@ export function ParamsEditor() {
                         updateParams({ initial_infections })
                     }
                 />
+                {introductions && (
+                    <small>
+                        Chance that one introduced infection dies out:{" "}
+                        {params.population_fraction_labels
+                            .map((label, i) => {
+                                let days =
+                                    introductions.days_to_establishment[i];
+                                return `${label} ${(
+                                    introductions.p_extinction[i] * 100
+                                ).toFixed(0)}%${
+                                    Number.isFinite(days)
+                                        ? ` (otherwise sustained after ${days.toFixed(
+                                              0
+                                          )} days)`
+                                        : ""
+                                }`;
+                            })
+                            .join(", ")}
+                    </small>
+                )}
+            </FormGroup>
+            <FormGroup>
+                <GroupEditor
+                    label="Share of initial infections"
+                    value={
+                        params.initial_infection_shares ??
+                        params.population_fractions
+                    }
+                    onValue={(initial_infection_shares) =>
+                        updateParams({ initial_infection_shares })
+                    }
+                    renderInput={(value, onValue) => (
+                        <NumberInput
+                            min={0.0}
+                            max={1.0}
+                            step={0.05}
+                            value={value}
+                            numberType="float"
+                            onValue={onValue}
+                        />
+                    )}
+                />
+            </FormGroup>
+            <FormGroup>
+                <GroupEditor
+                    label="Initially immune from past infection"
+                    value={params.initial_immunity}
+                    onValue={(initial_immunity) =>
+                        updateParams({ initial_immunity })
+                    }
+                    renderInput={(value, onValue) => (
+                        <NumberInput
+                            range
+                            min={0.0}
+                            max={1.0}
+                            step={0.05}
+                            value={value}
+                            numberType="float"
+                            onValue={onValue}
+                        />
+                    )}
+                />
+            </FormGroup>
+            <FormGroup>
+                <GroupEditor
+                    label="Initially vaccinated"
+                    value={params.initial_vaccinated}
+                    onValue={(initial_vaccinated) =>
+                        updateParams({ initial_vaccinated })
+                    }
+                    renderInput={(value, onValue) => (
+                        <NumberInput
+                            range
+                            min={0.0}
+                            max={1.0}
+                            step={0.05}
+                            value={value}
+                            numberType="float"
+                            onValue={onValue}
+                        />
+                    )}
+                />
+            </FormGroup>
+            <FormGroup>
+                <label>Imported infections per day</label>
+                <small>From the start day until the next change</small>
+                {importations.map(({ start, rate }, index) => (
+                    <div key={index}>
+                        <label>Start day</label>
+                        <NumberInput
+                            min={0}
+                            max={days}
+                            step={1}
+                            value={start}
+                            numberType="int"
+                            onValue={(start) =>
+                                updateImportation(index, { start })
+                            }
+                        />
+                        <label>Infections per day</label>
+                        <NumberInput
+                            min={0}
+                            step={1}
+                            value={rate}
+                            numberType="float"
+                            onValue={(rate) =>
+                                updateImportation(index, { rate })
+                            }
+                        />
+                        <button
+                            onClick={() =>
+                                updateParams({
+                                    importations: importations.filter(
+                                        (_, i) => i !== index
+                                    ),
+                                })
+                            }
+                        >
+                            Remove change
+                        </button>
+                    </div>
+                ))}
+                <button
+                    onClick={() => {
+                        let last = importations[importations.length - 1];
+                        updateParams({
+                            importations: [
+                                ...importations,
+                                last
+                                    ? {
+                                          ...last,
+                                          start: Math.min(last.start + 30, days),
+                                      }
+                                    : { start: 0, rate: 1, group_shares: null },
+                            ],
+                        });
+                    }}
+                >
+                    Add change
+                </button>
+            </FormGroup>
+            <FormGroup>
+                <label>
+                    <input
+                        type="checkbox"
+                        checked={variant != null}
+                        onChange={(e) =>
+                            updateParams({
+                                variant: e.target.checked
+                                    ? {
+                                          relative_transmissibility: 1.5,
+                                          introduction_day: 30,
+                                          introduced_infections: 10,
+                                          vaccine_escape: 0,
+                                          infection_escape: 0,
+                                      }
+                                    : null,
+                            })
+                        }
+                    />{" "}
+                    Second variant
+                </label>
+                {variant != null && (
+                    <>
+                        <label>Transmissibility relative to first strain</label>
+                        <NumberInput
+                            range
+                            min={0.5}
+                            max={3}
+                            step={0.1}
+                            value={variant.relative_transmissibility}
+                            numberType="float"
+                            onValue={(relative_transmissibility) =>
+                                updateParams({
+                                    variant: {
+                                        ...variant,
+                                        relative_transmissibility,
+                                    },
+                                })
+                            }
+                        />
+                        <label>Introduction day</label>
+                        <NumberInput
+                            min={0}
+                            max={days}
+                            step={1}
+                            value={variant.introduction_day}
+                            numberType="int"
+                            onValue={(introduction_day) =>
+                                updateParams({
+                                    variant: { ...variant, introduction_day },
+                                })
+                            }
+                        />
+                        <label>Infections introduced</label>
+                        <NumberInput
+                            min={0}
+                            step={10}
+                            value={variant.introduced_infections}
+                            numberType="float"
+                            onValue={(introduced_infections) =>
+                                updateParams({
+                                    variant: {
+                                        ...variant,
+                                        introduced_infections,
+                                    },
+                                })
+                            }
+                        />
+                        <label>Escape from vaccine efficacy</label>
+                        <NumberInput
+                            range
+                            min={0}
+                            max={1}
+                            step={0.05}
+                            value={variant.vaccine_escape}
+                            numberType="float"
+                            onValue={(vaccine_escape) =>
+                                updateParams({
+                                    variant: { ...variant, vaccine_escape },
+                                })
+                            }
+                        />
+                        <label>Escape from immunity after infection</label>
+                        <NumberInput
+                            range
+                            min={0}
+                            max={1}
+                            step={0.05}
+                            value={variant.infection_escape}
+                            numberType="float"
+                            onValue={(infection_escape) =>
+                                updateParams({
+                                    variant: { ...variant, infection_escape },
+                                })
+                            }
+                        />
+                    </>
+                )}
+            </FormGroup>
+            <FormGroup>
+                <label>
+                    <input
+                        type="checkbox"
+                        checked={stochastic != null}
+                        onChange={(e) =>
+                            updateParams({
+                                stochastic: e.target.checked
+                                    ? { seed: 1, runs: 50 }
+                                    : null,
+                            })
+                        }
+                    />{" "}
+                    Stochastic runs
+                </label>
+                {stochastic != null && (
+                    <>
+                        <small>Median and 90% range of the runs</small>
+                        <label>Number of runs</label>
+                        <NumberInput
+                            min={1}
+                            max={500}
+                            step={10}
+                            value={stochastic.runs}
+                            numberType="int"
+                            onValue={(runs) =>
+                                updateParams({
+                                    stochastic: { ...stochastic, runs },
+                                })
+                            }
+                        />
+                        <label>Random seed</label>
+                        <NumberInput
+                            min={0}
+                            step={1}
+                            value={stochastic.seed}
+                            numberType="int"
+                            onValue={(seed) =>
+                                updateParams({
+                                    stochastic: { ...stochastic, seed },
+                                })
+                            }
+                        />
+                    </>
+                )}
+            </FormGroup>
+            <FormGroup>
+                <label>Specify transmission by</label>
+                <SelectInput
+                    value={transmissionInput}
+                    options={transmissionInputs}
+                    onChange={(option: unknown) => {
+                        if (option) {
+                            setTransmissionInput(
+                                option as (typeof transmissionInputs)[number]
+                            );
+                        }
+                    }}
+                />
+            </FormGroup>
+            <FormGroup>
+                {transmissionInput.value === "r0" ? (
+                    <>
+                        <label>R0</label>
+                        <NumberInput
+                            range
+                            min={0.8}
+                            max={2.2}
+                            step={0.1}
+                            value={params.r0}
+                            numberType="float"
+                            onValue={(r0) => updateParams({ r0 })}
+                        />
+                    </>
+                ) : (
+                    <>
+                        <label>Doubling time (days)</label>
+                        {doublingTime === null ? (
+                            <small>
+                                Unavailable until the parameters are valid
+                            </small>
+                        ) : (
+                            <NumberInput
+                                min={0.5}
+                                step={0.5}
+                                value={doublingTime}
+                                numberType="float"
+                                onValue={(doubling_time) => {
+                                    try {
+                                        updateParams({
+                                            r0: r0_from_doubling_time(
+                                                params,
+                                                doubling_time
+                                            ),
+                                        });
+                                    } catch {
+                                        // Keep R0 as it is
+                                    }
+                                }}
+                            />
+                        )}
+                        <small>R0 {params.r0.toFixed(2)}</small>
+                    </>
+                )}
+                {reproductionNumbers && (
+                    <small>
+                        Infections caused by one infection:{" "}
+                        {params.population_fraction_labels
+                            .map(
+                                (label, i) =>
+                                    `${label} ${reproductionNumbers.by_group[
+                                        i
+                                    ].toFixed(2)}`
+                            )
+                            .join(", ")}
+                    </small>
+                )}
             </FormGroup>
             <FormGroup>
-                <label>R0</label>
+                <label>Seasonal amplitude</label>
                 <NumberInput
                     range
-                    min={0.8}
-                    max={2.2}
-                    step={0.1}
-                    value={params.r0}
+                    min={0}
+                    max={0.5}
+                    step={0.05}
+                    value={params.seasonal_amplitude}
                     numberType="float"
-                    onValue={(r0) => updateParams({ r0 })}
+                    onValue={(seasonal_amplitude) =>
+                        updateParams({ seasonal_amplitude })
+                    }
                 />
             </FormGroup>
+            {params.seasonal_amplitude > 0 && (
+                <FormGroup>
+                    <label>Seasonal peak day</label>
+                    <NumberInput
+                        min={0}
+                        max={365}
+                        step={1}
+                        value={params.seasonal_peak_day}
+                        numberType="int"
+                        onValue={(seasonal_peak_day) =>
+                            updateParams({ seasonal_peak_day })
+                        }
+                    />
+                </FormGroup>
+            )}
             <FormGroup>
                 <label>Latent period</label>
                 <NumberInput
@@ -92,6 +508,45 @@ export function ParamsEditor() {
                 />
             </FormGroup>
 
+            <FormGroup>
+                <GroupEditor
+                    label="Relative susceptibility"
+                    value={params.relative_susceptibility}
+                    onValue={(newValue) =>
+                        updateParams({ relative_susceptibility: newValue })
+                    }
+                    renderInput={(value, onValue) => (
+                        <NumberInput
+                            min={0.0}
+                            max={3.0}
+                            step={0.1}
+                            value={value}
+                            numberType="float"
+                            onValue={onValue}
+                        />
+                    )}
+                />
+            </FormGroup>
+            <FormGroup>
+                <GroupEditor
+                    label="Relative infectiousness"
+                    value={params.relative_infectiousness}
+                    onValue={(newValue) =>
+                        updateParams({ relative_infectiousness: newValue })
+                    }
+                    renderInput={(value, onValue) => (
+                        <NumberInput
+                            min={0.0}
+                            max={3.0}
+                            step={0.1}
+                            value={value}
+                            numberType="float"
+                            onValue={onValue}
+                        />
+                    )}
+                />
+            </FormGroup>
+
             <Mitigations />
 
             <FormGroup>
@@ -113,6 +568,20 @@ export function ParamsEditor() {
                     )}
                 />
             </FormGroup>
+            <FormGroup>
+                <label>Relative infectiousness of asymptomatic infections</label>
+                <NumberInput
+                    range
+                    min={0.0}
+                    max={1.0}
+                    step={0.05}
+                    numberType="float"
+                    value={params.asymptomatic_relative_infectiousness}
+                    onValue={(asymptomatic_relative_infectiousness) =>
+                        updateParams({ asymptomatic_relative_infectiousness })
+                    }
+                />
+            </FormGroup>
             <FormGroup>
                 <GroupEditor
                     label="Percent hospitalized"
@@ -132,6 +601,109 @@ export function ParamsEditor() {
                     )}
                 />
             </FormGroup>
+            <FormGroup>
+                <GroupEditor
+                    label="Hospital length of stay (days)"
+                    value={params.hospital_length_of_stay}
+                    onValue={(newValue) =>
+                        updateParams({ hospital_length_of_stay: newValue })
+                    }
+                    renderInput={(value, onValue) => (
+                        <NumberInput
+                            min={1}
+                            max={30}
+                            step={0.5}
+                            value={value}
+                            numberType="float"
+                            onValue={onValue}
+                        />
+                    )}
+                />
+            </FormGroup>
+            <FormGroup>
+                <GroupEditor
+                    label="Percent of hospitalized in ICU"
+                    value={params.fraction_icu}
+                    onValue={(newValue) =>
+                        updateParams({ fraction_icu: newValue })
+                    }
+                    renderInput={(value, onValue) => (
+                        <NumberInput
+                            min={0.0}
+                            max={1.0}
+                            step={0.01}
+                            value={value}
+                            numberType="float"
+                            onValue={onValue}
+                        />
+                    )}
+                />
+            </FormGroup>
+            <FormGroup>
+                <GroupEditor
+                    label="ICU length of stay (days)"
+                    value={params.icu_length_of_stay}
+                    onValue={(newValue) =>
+                        updateParams({ icu_length_of_stay: newValue })
+                    }
+                    renderInput={(value, onValue) => (
+                        <NumberInput
+                            min={1}
+                            max={60}
+                            step={0.5}
+                            value={value}
+                            numberType="float"
+                            onValue={onValue}
+                        />
+                    )}
+                />
+            </FormGroup>
+            <FormGroup>
+                <label>
+                    <input
+                        type="checkbox"
+                        checked={params.icu_capacity != null}
+                        onChange={(e) =>
+                            updateParams({
+                                icu_capacity: e.target.checked
+                                    ? 10_000
+                                    : null,
+                            })
+                        }
+                    />{" "}
+                    Limit ICU beds
+                </label>
+                {params.icu_capacity != null && (
+                    <NumberInput
+                        min={0}
+                        step={1_000}
+                        value={params.icu_capacity}
+                        numberType="int"
+                        onValue={(icu_capacity) =>
+                            updateParams({ icu_capacity })
+                        }
+                    />
+                )}
+            </FormGroup>
+            <FormGroup>
+                <GroupEditor
+                    label="Excess fatality without ICU bed"
+                    value={params.fraction_dead_icu_overflow}
+                    onValue={(newValue) =>
+                        updateParams({ fraction_dead_icu_overflow: newValue })
+                    }
+                    renderInput={(value, onValue) => (
+                        <NumberInput
+                            min={0.0}
+                            max={1.0}
+                            step={0.05}
+                            value={value}
+                            numberType="float"
+                            onValue={onValue}
+                        />
+                    )}
+                />
+            </FormGroup>
             <FormGroup>
                 <GroupEditor
                     label="Fatality rate"
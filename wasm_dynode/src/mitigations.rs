@@ -1,7 +1,9 @@
-use nalgebra::SMatrix;
+use nalgebra::{Const, DefaultAllocator, Dim, DimName, OMatrix, allocator::Allocator};
 use serde::{Deserialize, Serialize};
 use tsify::Tsify;
 
+use crate::ContactSetting;
+
 pub trait Mitigation {
     // Is the mitigation applied to the model?
     fn get_enabled(&self) -> bool;
@@ -11,18 +13,165 @@ pub trait Mitigation {
     fn set_editable(&mut self, editable: bool);
 }
 
+/// Who gets vaccinated among the people in each group
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum VaccineTargeting {
+    /// Everyone, whatever their disease state
+    All,
+    /// Only people without infection-derived antibodies, i.e. everyone but
+    /// the recovered, whether or not their infection was symptomatic. People
+    /// who are exposed or infectious have not seroconverted yet, and people
+    /// whose immunity from infection has waned count as seronegative again.
+    Seronegative,
+    /// Only susceptible people
+    Susceptible,
+}
+
+/// How first doses are shared between population groups
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum VaccineAllocation {
+    /// In proportion to the size of each group
+    ProRata,
+    /// To one group at a time, in the given order of group indices, moving
+    /// on to the next group once a group's coverage cap is reached
+    Priority(Vec<usize>),
+    /// In the given proportions, one per group
+    Shares(Vec<f64>),
+}
+
+/// Doses added to the vaccine stockpile on a given day
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct VaccineDelivery {
+    pub day: f64,
+    pub doses: f64,
+}
+
+/// Number of doses that can be administered per day from a given day on
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct AdministrationRateChange {
+    pub day: f64,
+    pub rate: f64,
+}
+
+/// Settings of the vaccination campaign. Doses are given on a schedule that
+/// is planned from the administration rate, the deliveries and the dose
+/// interval before the model runs, whoever is left to vaccinate. Doses planned
+/// while no one can receive them, because of coverage caps, targeting or too
+/// few people left, are not given and not made up later, but the schedule
+/// still draws them from its stockpile, and follow-up doses are due one dose
+/// interval after the planned previous doses. The stockpile output only
+/// counts the doses actually administered, so it keeps the doses that were
+/// planned but not given.
 #[derive(Tsify, Debug, Clone, Serialize, Deserialize)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct VaccineParams {
     pub enabled: bool,
     pub editable: bool,
     pub doses: usize,
+    // Days between successive doses of a multi-dose schedule
+    pub dose_interval: f64,
     pub start: f64,
+    // Administration rate and doses available at the start of vaccination
     pub administration_rate: f64,
     pub doses_available: f64,
+    // Later changes to the administration rate and deliveries of doses
+    pub administration_rate_changes: Vec<AdministrationRateChange>,
+    pub deliveries: Vec<VaccineDelivery>,
+    // Efficacy once all doses have been received
     pub ve_s: f64,
     pub ve_i: f64,
     pub ve_p: f64,
+    // Efficacy after each dose before the last one
+    pub partial_ve_s: Vec<f64>,
+    pub partial_ve_i: Vec<f64>,
+    pub partial_ve_p: Vec<f64>,
+    // Days between receiving a dose and being protected by it
+    pub ramp_up_period: f64,
+    pub targeting: VaccineTargeting,
+    pub allocation: VaccineAllocation,
+    // Maximum fraction of each group that gets vaccinated, e.g. because of
+    // vaccine hesitancy. Empty for no caps.
+    pub coverage_caps: Vec<f64>,
+}
+
+impl VaccineParams {
+    /// Efficacy (VE_S, VE_I, VE_P) of the vaccine after `dose + 1` doses
+    pub fn efficacy(&self, dose: usize) -> (f64, f64, f64) {
+        if dose + 1 < self.doses {
+            (
+                self.partial_ve_s[dose],
+                self.partial_ve_i[dose],
+                self.partial_ve_p[dose],
+            )
+        } else {
+            (self.ve_s, self.ve_i, self.ve_p)
+        }
+    }
+
+    /// Coverage cap of `group`
+    pub fn coverage_cap(&self, group: usize) -> f64 {
+        self.coverage_caps.get(group).copied().unwrap_or(1.0)
+    }
+
+    fn validate(&self, n: usize) -> Result<(), &'static str> {
+        if self.doses == 0 {
+            return Err("Invalid number of vaccine doses");
+        }
+        if [&self.partial_ve_s, &self.partial_ve_i, &self.partial_ve_p]
+            .iter()
+            .any(|ve| ve.len() != self.doses - 1)
+        {
+            return Err("Invalid number of partial vaccine efficacies");
+        }
+        if self.doses > 1 && self.dose_interval <= 0.0 {
+            return Err("Invalid vaccine dose interval");
+        }
+        if self.administration_rate < 0.0
+            || self.doses_available < 0.0
+            || self
+                .administration_rate_changes
+                .iter()
+                .any(|change| !change.day.is_finite() || change.rate < 0.0)
+            || self
+                .deliveries
+                .iter()
+                .any(|delivery| !delivery.day.is_finite() || delivery.doses < 0.0)
+        {
+            return Err("Invalid vaccine supply");
+        }
+        if self.ramp_up_period < 0.0 {
+            return Err("Invalid vaccine ramp-up period");
+        }
+        match &self.allocation {
+            VaccineAllocation::ProRata => {}
+            VaccineAllocation::Priority(order) => {
+                let mut sorted = order.clone();
+                sorted.sort_unstable();
+                if !sorted.into_iter().eq(0..n) {
+                    return Err("Invalid vaccine priority order");
+                }
+            }
+            VaccineAllocation::Shares(shares) => {
+                if shares.len() != n || shares.iter().any(|share| *share < 0.0) {
+                    return Err("Invalid vaccine allocation shares");
+                }
+            }
+        }
+        if !self.coverage_caps.is_empty()
+            && (self.coverage_caps.len() != n
+                || self
+                    .coverage_caps
+                    .iter()
+                    .any(|cap| !(0.0..=1.0).contains(cap)))
+        {
+            return Err("Invalid vaccine coverage caps");
+        }
+        Ok(())
+    }
 }
 
 #[derive(Tsify, Debug, Clone, Serialize, Deserialize)]
@@ -38,51 +187,179 @@ pub struct AntiviralsParams {
     pub ave_p: f64,
 }
 
+/// What puts a community mitigation period in effect. Mitigations that are
+/// triggered by the state of the epidemic start no earlier than their `start`
+/// day, and each period is triggered at most once.
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum MitigationTrigger {
+    /// On the `start` day
+    Day,
+    /// Once daily hospital admissions per 100,000 people reach a threshold
+    HospitalAdmissions(f64),
+    /// Once the probability of detection reaches a threshold
+    Detection(f64),
+}
+
+/// What lifts a community mitigation period
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum MitigationRelease {
+    /// `duration` days after it was put in effect
+    Duration,
+    /// Once daily hospital admissions per 100,000 people fall below a threshold
+    HospitalAdmissions(f64),
+}
+
+/// Reduction in the contacts made in one setting
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SettingEffectiveness {
+    pub setting: ContactSetting,
+    pub effectiveness: f64,
+}
+
+/// A period of community mitigation, with its own effectiveness at reducing
+/// contacts between each pair of groups, and in each setting
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CommunityMitigationPeriodExport {
+    pub start: f64,
+    pub duration: f64,
+    pub effectiveness: Vec<f64>,
+    pub setting_effectiveness: Vec<SettingEffectiveness>,
+    pub trigger: MitigationTrigger,
+    pub release: MitigationRelease,
+}
+
 #[derive(Tsify, Debug, Clone, Serialize, Deserialize)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct CommunityMitigationParamsExport {
     pub enabled: bool,
     pub editable: bool,
+    pub periods: Vec<CommunityMitigationPeriodExport>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GenericCommunityMitigationPeriod<D: Dim>
+where
+    DefaultAllocator: Allocator<D, D>,
+{
     pub start: f64,
     pub duration: f64,
-    pub effectiveness: Vec<f64>,
+    pub effectiveness: OMatrix<f64, D, D>,
+    pub setting_effectiveness: Vec<SettingEffectiveness>,
+    pub trigger: MitigationTrigger,
+    pub release: MitigationRelease,
 }
 
+pub type CommunityMitigationPeriod<const N: usize> = GenericCommunityMitigationPeriod<Const<N>>;
+
 #[derive(Debug, Clone)]
-pub struct CommunityMitigationParams<const N: usize> {
+pub struct GenericCommunityMitigationParams<D: Dim>
+where
+    DefaultAllocator: Allocator<D, D>,
+{
     pub enabled: bool,
     pub editable: bool,
-    pub start: f64,
-    pub duration: f64,
-    pub effectiveness: SMatrix<f64, N, N>,
+    // Overlapping periods compose multiplicatively
+    pub periods: Vec<GenericCommunityMitigationPeriod<D>>,
 }
 
-impl<const N: usize> From<CommunityMitigationParams<N>> for CommunityMitigationParamsExport {
-    fn from(value: CommunityMitigationParams<N>) -> Self {
+pub type CommunityMitigationParams<const N: usize> = GenericCommunityMitigationParams<Const<N>>;
+
+impl<D: Dim> From<GenericCommunityMitigationParams<D>> for CommunityMitigationParamsExport
+where
+    DefaultAllocator: Allocator<D, D>,
+{
+    fn from(value: GenericCommunityMitigationParams<D>) -> Self {
         CommunityMitigationParamsExport {
             enabled: value.enabled,
             editable: value.editable,
-            start: value.start,
-            duration: value.duration,
-            effectiveness: value.effectiveness.data.as_slice().into(),
+            periods: value
+                .periods
+                .into_iter()
+                .map(|period| CommunityMitigationPeriodExport {
+                    start: period.start,
+                    duration: period.duration,
+                    effectiveness: period.effectiveness.iter().copied().collect(),
+                    setting_effectiveness: period.setting_effectiveness,
+                    trigger: period.trigger,
+                    release: period.release,
+                })
+                .collect(),
         }
     }
 }
 
-impl<const N: usize> TryFrom<CommunityMitigationParamsExport> for CommunityMitigationParams<N> {
-    type Error = &'static str;
-
-    fn try_from(value: CommunityMitigationParamsExport) -> Result<Self, Self::Error> {
-        Ok(CommunityMitigationParams {
+impl<D: Dim> GenericCommunityMitigationParams<D>
+where
+    DefaultAllocator: Allocator<D, D>,
+{
+    /// Convert from the exported parameters, for `d` population groups
+    pub fn try_from_export(
+        value: CommunityMitigationParamsExport,
+        d: D,
+    ) -> Result<Self, &'static str> {
+        let n = d.value();
+        let periods = value
+            .periods
+            .into_iter()
+            .map(|period| {
+                if period.effectiveness.len() != n * n {
+                    return Err("Invalid number of community mitigation effectiveness elements");
+                }
+                if period.duration < 0.0 {
+                    return Err("Invalid community mitigation duration");
+                }
+                let invalid_trigger = match period.trigger {
+                    MitigationTrigger::Day => false,
+                    MitigationTrigger::HospitalAdmissions(threshold) => threshold < 0.0,
+                    MitigationTrigger::Detection(threshold) => !(0.0..=1.0).contains(&threshold),
+                };
+                let invalid_release = match period.release {
+                    MitigationRelease::Duration => false,
+                    MitigationRelease::HospitalAdmissions(threshold) => threshold < 0.0,
+                };
+                if invalid_trigger || invalid_release {
+                    return Err("Invalid community mitigation trigger");
+                }
+                if period
+                    .setting_effectiveness
+                    .iter()
+                    .any(|setting| setting.effectiveness > 1.0)
+                {
+                    return Err("Invalid community mitigation setting effectiveness");
+                }
+                Ok(GenericCommunityMitigationPeriod {
+                    start: period.start,
+                    duration: period.duration,
+                    effectiveness: OMatrix::from_iterator_generic(d, d, period.effectiveness),
+                    setting_effectiveness: period.setting_effectiveness,
+                    trigger: period.trigger,
+                    release: period.release,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(GenericCommunityMitigationParams {
             enabled: value.enabled,
             editable: value.editable,
-            start: value.start,
-            duration: value.duration,
-            effectiveness: SMatrix::from_iterator(value.effectiveness.into_iter()),
+            periods,
         })
     }
 }
 
+impl<D: DimName> TryFrom<CommunityMitigationParamsExport> for GenericCommunityMitigationParams<D>
+where
+    DefaultAllocator: Allocator<D, D>,
+{
+    type Error = &'static str;
+
+    fn try_from(value: CommunityMitigationParamsExport) -> Result<Self, Self::Error> {
+        Self::try_from_export(value, D::name())
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, tsify::Tsify)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct MitigationParamsExport {
@@ -92,25 +369,43 @@ pub struct MitigationParamsExport {
 }
 
 #[derive(Debug, Clone)]
-pub struct MitigationParams<const N: usize> {
+pub struct GenericMitigationParams<D: Dim>
+where
+    DefaultAllocator: Allocator<D, D>,
+{
     pub vaccine: VaccineParams,
     pub antivirals: AntiviralsParams,
-    pub community: CommunityMitigationParams<N>,
+    pub community: GenericCommunityMitigationParams<D>,
 }
 
-impl<const N: usize> Default for MitigationParams<N> {
+pub type MitigationParams<const N: usize> = GenericMitigationParams<Const<N>>;
+
+impl<D: DimName> Default for GenericMitigationParams<D>
+where
+    DefaultAllocator: Allocator<D, D>,
+{
     fn default() -> Self {
-        MitigationParams {
+        GenericMitigationParams {
             vaccine: VaccineParams {
                 enabled: false,
                 editable: true,
                 doses: 1,
+                dose_interval: 28.0,
                 start: 0.0,
                 administration_rate: 1_500_000.0,
                 doses_available: 40_000_000.0,
+                administration_rate_changes: vec![],
+                deliveries: vec![],
                 ve_s: 0.5,
                 ve_i: 0.5,
                 ve_p: 0.5,
+                partial_ve_s: vec![],
+                partial_ve_i: vec![],
+                partial_ve_p: vec![],
+                ramp_up_period: 0.0,
+                targeting: VaccineTargeting::All,
+                allocation: VaccineAllocation::ProRata,
+                coverage_caps: vec![],
             },
             antivirals: AntiviralsParams {
                 enabled: false,
@@ -122,19 +417,27 @@ impl<const N: usize> Default for MitigationParams<N> {
                 fraction_diagnosed_prescribed_outpatient: 0.7,
                 fraction_seek_care: 0.6,
             },
-            community: CommunityMitigationParams {
+            community: GenericCommunityMitigationParams {
                 enabled: false,
                 editable: true,
-                start: 60.0,
-                duration: 20.0,
-                effectiveness: SMatrix::from_element(0.25),
+                periods: vec![GenericCommunityMitigationPeriod {
+                    start: 60.0,
+                    duration: 20.0,
+                    effectiveness: OMatrix::from_element(0.25),
+                    setting_effectiveness: vec![],
+                    trigger: MitigationTrigger::Day,
+                    release: MitigationRelease::Duration,
+                }],
             },
         }
     }
 }
 
-impl<const N: usize> From<MitigationParams<N>> for MitigationParamsExport {
-    fn from(value: MitigationParams<N>) -> Self {
+impl<D: Dim> From<GenericMitigationParams<D>> for MitigationParamsExport
+where
+    DefaultAllocator: Allocator<D, D>,
+{
+    fn from(value: GenericMitigationParams<D>) -> Self {
         MitigationParamsExport {
             vaccine: value.vaccine,
             antivirals: value.antivirals,
@@ -143,14 +446,28 @@ impl<const N: usize> From<MitigationParams<N>> for MitigationParamsExport {
     }
 }
 
-impl<const N: usize> TryFrom<MitigationParamsExport> for MitigationParams<N> {
-    type Error = &'static str;
-
-    fn try_from(value: MitigationParamsExport) -> Result<Self, Self::Error> {
-        Ok(MitigationParams {
+impl<D: Dim> GenericMitigationParams<D>
+where
+    DefaultAllocator: Allocator<D, D>,
+{
+    /// Convert from the exported parameters, for `d` population groups
+    pub fn try_from_export(value: MitigationParamsExport, d: D) -> Result<Self, &'static str> {
+        value.vaccine.validate(d.value())?;
+        Ok(GenericMitigationParams {
             vaccine: value.vaccine,
             antivirals: value.antivirals,
-            community: CommunityMitigationParams::try_from(value.community)?,
+            community: GenericCommunityMitigationParams::try_from_export(value.community, d)?,
         })
     }
 }
+
+impl<D: DimName> TryFrom<MitigationParamsExport> for GenericMitigationParams<D>
+where
+    DefaultAllocator: Allocator<D, D>,
+{
+    type Error = &'static str;
+
+    fn try_from(value: MitigationParamsExport) -> Result<Self, Self::Error> {
+        Self::try_from_export(value, D::name())
+    }
+}
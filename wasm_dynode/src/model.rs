@@ -1,44 +1,67 @@
-use crate::{DynodeModel, ModelOutput, Parameters};
-use nalgebra::{Const, Matrix, MatrixView, SMatrix, SVector, Storage, StorageMut};
-use ode_solvers::{Dopri5, System};
+use crate::{
+    DynodeModel, GenericCommunityMitigationPeriod, GenericParameters, Introductions,
+    MitigationRelease, MitigationTrigger, ModelOutput, ReproductionNumbers, VaccineAllocation,
+    VaccineParams, VaccineTargeting,
+};
+use nalgebra::{
+    Const, DVector, DefaultAllocator, Dim, Dyn, Matrix, MatrixView, OMatrix, OVector, Storage, U1,
+    allocator::Allocator,
+};
+use ode_solvers::{Dopri5, System, dop_shared::OutputType as SolverOutputType};
 use paste::paste;
 
-pub struct AVE<const N: usize> {
-    pub rr_i: SVector<f64, N>,
-    pub rr_p_hosp: SVector<f64, N>,
-    pub rr_p_death: SVector<f64, N>,
+mod stochastic;
+pub use stochastic::*;
+
+pub struct AVE<D: Dim>
+where
+    DefaultAllocator: Allocator<D>,
+{
+    pub rr_i: OVector<f64, D>,
+    pub rr_p_hosp: OVector<f64, D>,
+    pub rr_p_death: OVector<f64, D>,
 }
 
-impl<const N: usize> AVE<N> {
-    fn new(params: &Parameters<N>) -> Self {
+impl<D: Dim> AVE<D>
+where
+    DefaultAllocator: Allocator<D>,
+{
+    fn new(params: &GenericParameters<D>) -> Self
+    where
+        DefaultAllocator: Allocator<D, D>,
+    {
         let av_params = &params.mitigations.antivirals;
-        let ones = SVector::<f64, N>::from_element(1.0);
+        let (d, _) = params.population_fractions.shape_generic();
+        let ones = OVector::<f64, D>::from_element_generic(d, U1, 1.0);
 
-        // risk ratio (proportional reduction) against transmission
+        // risk ratio (proportional reduction) against transmission from
+        // symptomatic infections, the only ones treated with antivirals
         let rr_i = if av_params.enabled {
-            ones - params.fraction_symptomatic
-                * av_params.fraction_seek_care
-                * av_params.fraction_diagnosed_prescribed_outpatient
-                * av_params.fraction_adhere
-                * av_params.ave_i
+            &ones
+                * (1.0
+                    - av_params.fraction_seek_care
+                        * av_params.fraction_diagnosed_prescribed_outpatient
+                        * av_params.fraction_adhere
+                        * av_params.ave_i)
         } else {
-            ones
+            ones.clone()
         };
 
         // risk ratio against hospitalization given infection
         let rr_p_hosp = if av_params.enabled {
-            ones - params.fraction_symptomatic
-                * av_params.fraction_seek_care
-                * av_params.fraction_diagnosed_prescribed_outpatient
-                * av_params.fraction_adhere
-                * av_params.ave_p
+            &ones
+                - &params.fraction_symptomatic
+                    * av_params.fraction_seek_care
+                    * av_params.fraction_diagnosed_prescribed_outpatient
+                    * av_params.fraction_adhere
+                    * av_params.ave_p
         } else {
-            ones
+            ones.clone()
         };
 
         // risk ratio against death given infection
         let rr_p_death = if av_params.enabled {
-            (1.0 - av_params.fraction_diagnosed_prescribed_inpatient * av_params.ave_p) * rr_p_hosp
+            (1.0 - av_params.fraction_diagnosed_prescribed_inpatient * av_params.ave_p) * &rr_p_hosp
         } else {
             ones
         };
@@ -51,433 +74,2683 @@ impl<const N: usize> AVE<N> {
     }
 }
 
-pub struct SEIRModel<const N: usize> {
-    pub(crate) parameters: Parameters<N>,
+/// Number of doses administered per day, by dose number, over the course of a
+/// vaccination campaign. Doses are drawn from a stockpile, which holds the
+/// doses available at the start of the campaign and is topped up by later
+/// deliveries, at the administration rate in effect until the stockpile runs
+/// out. Each follow-up dose is due one dose interval after the previous one.
+/// Due doses take priority over first doses, which get the rest of the
+/// capacity, and due doses that could not be given are given when possible.
+struct VaccineSchedule {
+    // Start of each period over which the rates are constant
+    times: Vec<f64>,
+    // Rates by dose number over each period
+    rates: Vec<Vec<f64>>,
+    // Time and number of doses of each delivery, including the initial doses
+    deliveries: Vec<(f64, f64)>,
+}
+
+/// Rate of `dose` at time `x`, from the periods of constant rates so far
+fn scheduled_rate(times: &[f64], rates: &[Vec<f64>], x: f64, dose: usize) -> f64 {
+    match times.partition_point(|time| *time <= x) {
+        0 => 0.0,
+        k => rates[k - 1][dose],
+    }
+}
+
+impl VaccineSchedule {
+    fn new(params: &VaccineParams) -> Self {
+        if !params.enabled {
+            return Self {
+                times: vec![],
+                rates: vec![],
+                deliveries: vec![],
+            };
+        }
+        let start = params.start;
+        let doses = params.doses;
+        // Doses delivered or rates changed before the start of vaccination
+        // only take effect from the start
+        let mut deliveries = vec![(start, params.doses_available)];
+        deliveries.extend(
+            params
+                .deliveries
+                .iter()
+                .map(|delivery| (delivery.day.max(start), delivery.doses)),
+        );
+        deliveries.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut rate_changes = vec![(start, params.administration_rate)];
+        rate_changes.extend(
+            params
+                .administration_rate_changes
+                .iter()
+                .map(|change| (change.day.max(start), change.rate)),
+        );
+        rate_changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let interval = if doses > 1 {
+            params.dose_interval
+        } else {
+            f64::INFINITY
+        };
+        // Tolerance on numbers of doses, to absorb rounding errors
+        let eps = 1e-9
+            * deliveries
+                .iter()
+                .map(|(_, doses)| doses)
+                .sum::<f64>()
+                .max(1.0);
+
+        let mut times: Vec<f64> = Vec::new();
+        let mut rates: Vec<Vec<f64>> = Vec::new();
+        let mut t = start;
+        let mut stockpile = 0.0;
+        let mut delivered = 0;
+        // Doses that are due but could not be given yet, by dose number
+        let mut backlog = vec![0.0; doses];
+        loop {
+            while delivered < deliveries.len() && deliveries[delivered].0 <= t {
+                stockpile += deliveries[delivered].1;
+                delivered += 1;
+            }
+            let capacity = rate_changes
+                .iter()
+                .rfind(|(day, _)| *day <= t)
+                .map_or(0.0, |(_, rate)| *rate);
+            let due: Vec<f64> = (0..doses)
+                .map(|dose| match dose {
+                    0 => 0.0,
+                    _ => scheduled_rate(&times, &rates, t - interval, dose - 1),
+                })
+                .collect();
+
+            // Rates over the period starting at `t`
+            let mut remaining = if stockpile > eps { capacity } else { 0.0 };
+            let mut period_rates = vec![0.0; doses];
+            for dose in (1..doses).rev() {
+                period_rates[dose] = if backlog[dose] > eps {
+                    remaining
+                } else {
+                    due[dose].min(remaining)
+                };
+                remaining -= period_rates[dose];
+            }
+            period_rates[0] = remaining;
+            let total_rate: f64 = period_rates.iter().sum();
+            times.push(t);
+            rates.push(period_rates.clone());
+            if stockpile <= eps && delivered == deliveries.len() {
+                break;
+            }
+
+            // The period lasts until the next change in any of its inputs
+            let mut next = f64::INFINITY;
+            if let Some((day, _)) = deliveries.get(delivered) {
+                next = next.min(*day);
+            }
+            if let Some((day, _)) = rate_changes.iter().find(|(day, _)| *day > t) {
+                next = next.min(*day);
+            }
+            if let Some(time) = times
+                .iter()
+                .map(|time| time + interval)
+                .find(|time| *time > t)
+            {
+                next = next.min(time);
+            }
+            if total_rate > 0.0 {
+                next = next.min(t + stockpile / total_rate);
+            }
+            for dose in 1..doses {
+                if backlog[dose] > eps && period_rates[dose] > due[dose] {
+                    next = next.min(t + backlog[dose] / (period_rates[dose] - due[dose]));
+                }
+            }
+            if !next.is_finite() {
+                break;
+            }
+
+            let dt = next - t;
+            stockpile -= total_rate * dt;
+            if stockpile <= eps {
+                stockpile = 0.0;
+            }
+            for dose in 1..doses {
+                backlog[dose] += (due[dose] - period_rates[dose]) * dt;
+                if backlog[dose] <= eps {
+                    backlog[dose] = 0.0;
+                }
+            }
+            t = next;
+        }
+        Self {
+            times,
+            rates,
+            deliveries,
+        }
+    }
+
+    /// Number of `dose + 1`-th doses administered per day at time `x`
+    fn rate(&self, x: f64, dose: usize) -> f64 {
+        scheduled_rate(&self.times, &self.rates, x, dose)
+    }
+
+    /// Total number of doses delivered by time `x`
+    fn delivered(&self, x: f64) -> f64 {
+        self.deliveries
+            .iter()
+            .take_while(|(day, _)| *day <= x)
+            .map(|(_, doses)| doses)
+            .sum()
+    }
+}
+
+/// Position of the first group of a stage of a compartment
+type Slot = fn(&StateLayout, usize) -> usize;
+
+/// Compartments of the people infected with one strain, from the latent
+/// period to recovery, and the counter of their infections beyond the
+/// counters shared by all strains
+#[derive(Clone, Copy)]
+struct StrainSlots {
+    e: Slot,
+    i: Slot,
+    ia: Slot,
+    r: Slot,
+    ev: Slot,
+    iv: Slot,
+    iva: Slot,
+    rv: Slot,
+    cum: Option<Slot>,
+}
+
+const FIRST_STRAIN_SLOTS: StrainSlots = StrainSlots {
+    e: StateLayout::e_slot,
+    i: StateLayout::i_slot,
+    ia: StateLayout::ia_slot,
+    r: StateLayout::r_slot,
+    ev: StateLayout::ev_slot,
+    iv: StateLayout::iv_slot,
+    iva: StateLayout::iva_slot,
+    rv: StateLayout::rv_slot,
+    cum: None,
+};
+
+const VARIANT_SLOTS: StrainSlots = StrainSlots {
+    e: StateLayout::e_variant_slot,
+    i: StateLayout::i_variant_slot,
+    ia: StateLayout::ia_variant_slot,
+    r: StateLayout::r_variant_slot,
+    ev: StateLayout::ev_variant_slot,
+    iv: StateLayout::iv_variant_slot,
+    iva: StateLayout::iva_variant_slot,
+    rv: StateLayout::rv_variant_slot,
+    cum: Some(StateLayout::variant_cum_slot),
+};
+
+/// A strain of the pathogen circulating in the model
+struct Strain {
+    slots: StrainSlots,
+    // R0 relative to the first strain
+    transmissibility: f64,
+    // vaccine efficacy (VE_S, VE_I, VE_P) against the strain, by number of
+    // doses received
+    efficacy: Vec<(f64, f64, f64)>,
+    // fraction of the protection from past infection with the first strain
+    // lost against this one
+    infection_escape: f64,
+    introduction_day: usize,
+}
+
+/// SEIR model, generic over the dimension `D` of the population groups.
+///
+/// The model state is a flat, runtime-sized vector of compartments, each
+/// holding one value per group. Use [`SEIRModel<N>`] when the number of groups
+/// is known at compile time and [`SEIRModelDyn`] when it comes from user data.
+pub struct GenericSEIRModel<D: Dim>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    pub(crate) parameters: GenericParameters<D>,
+    d: D,
+    layout: StateLayout,
+    // contacts in all settings
+    contact_matrix: OMatrix<f64, D, D>,
     contact_matrix_normalization: f64,
-    ave: AVE<N>,
+    ave: AVE<D>,
+    vaccine_schedule: VaccineSchedule,
+    // the first strain, then the variant if any
+    strains: Vec<Strain>,
 }
 
+/// SEIR model for a compile-time number of groups `N`
+pub type SEIRModel<const N: usize> = GenericSEIRModel<Const<N>>;
+
+/// SEIR model for a number of groups only known at runtime
+pub type SEIRModelDyn = GenericSEIRModel<Dyn>;
+
+type State = DVector<f64>;
+
+// Solver tolerances
+const RTOL: f64 = 1e-6;
+const ATOL: f64 = 1e-6;
+
+// Step size control, as in `Dopri5::new`
+const SAFETY_FACTOR: f64 = 0.9;
+const BETA: f64 = 0.04;
+const FAC_MIN: f64 = 0.2;
+const FAC_MAX: f64 = 10.0;
+// Let the solver choose the initial step size
+const INITIAL_STEP: f64 = 0.0;
+const MAX_STEPS: u32 = 100_000;
+// Never test for stiffness
+const STIFFNESS_TEST_INTERVAL: u32 = u32::MAX;
+
+// Relative tolerance and maximum number of iterations of the power algorithm,
+// which converges long before for any realistic contact matrix
+const EIGEN_TOLERANCE: f64 = 1e-12;
+const MAX_EIGEN_ITERATIONS: usize = 100_000;
+
+// Tolerance and maximum number of iterations of the extinction probabilities,
+// which converge slowly when R0 is close to 1
+const EXTINCTION_TOLERANCE: f64 = 1e-12;
+const MAX_EXTINCTION_ITERATIONS: usize = 1_000_000;
+
+// Transmission is sustained once the infections in a growing epidemic would
+// all die out with less than this probability
+const ESTABLISHMENT_RISK: f64 = 0.01;
+
+// Groups with fewer people left who can receive a vaccine dose get no more doses
+const MIN_RECIPIENTS: f64 = 1.0;
+
 macro_rules! make_state {
-    ($( $x:ident),*) => {
-        type State<const N: usize> = SVector<f64, { ${count($x)} * N }>;
+    ($params:ident; $( $x:ident: $stages:expr ),*) => {
+        /// Number of compartments in the state
+        const N_COMPARTMENTS: usize = ${count($x)};
 
-        trait StateWrapper<const N: usize, S: Storage<f64, Const<{ ${count($x)} * N }>> + 'static>
-        where Self: 'static,
-        {
-            paste! {
-            $(
-            #[allow(dead_code)]
-            fn [<get_  $x>](&self) -> MatrixView<'_, f64, Const<N>, Const<1>, S::RStride, S::CStride>;
-            )*
-            }
+        /// Position of each compartment in a flat model state. Compartments
+        /// hold one value per group for each of their stages, with the stages
+        /// stored one after the other.
+        struct StateLayout {
+            n: usize,
+            stages: [usize; N_COMPARTMENTS],
+            offsets: [usize; N_COMPARTMENTS],
+            len: usize,
         }
 
-        impl<const N: usize, S: Storage<f64, Const<{ ${count($x)} * N }>> + 'static> StateWrapper<N, S>
-            for Matrix<f64, Const<{ ${count($x)} * N }>, Const<1>, S>
-        {
+        impl StateLayout {
+            fn new<D: Dim>($params: &GenericParameters<D>) -> Self
+            where
+                DefaultAllocator: Allocator<D> + Allocator<D, D>,
+            {
+                let n = $params.n();
+                let stages = [$( $stages ),*];
+                let mut offsets = [0; N_COMPARTMENTS];
+                let mut len = 0;
+                for (offset, stages) in offsets.iter_mut().zip(stages) {
+                    *offset = len;
+                    len += stages * n;
+                }
+                Self { n, stages, offsets, len }
+            }
+
+            fn start(&self, compartment: usize, stage: usize) -> usize {
+                assert!(stage < self.stages[compartment], "Invalid stage");
+                self.offsets[compartment] + stage * self.n
+            }
+
             paste! {
             $(
-                fn [<get_  $x>](&self) -> MatrixView<'_, f64, Const<N>, Const<1>, S::RStride, S::CStride> {
-                    self.fixed_view::<N, 1>(${index()} * N, 0)
+                /// Position of the first group of a stage of the compartment
+                #[allow(dead_code)]
+                fn [<$x _slot>](&self, stage: usize) -> usize {
+                    self.start(${index()}, stage)
                 }
             )*
+            }
         }
 
+        /// Named, per-group views into a model state
+        struct StateView<'a, D: Dim> {
+            y: &'a State,
+            layout: &'a StateLayout,
+            d: D,
         }
 
-        trait StateWrapperMut<const N: usize>
-        where Self: 'static,
+        impl<'a, D: Dim> StateView<'a, D>
+        where
+            DefaultAllocator: Allocator<D>,
         {
+            fn new(y: &'a State, layout: &'a StateLayout, d: D) -> Self {
+                Self { y, layout, d }
+            }
+
             paste! {
             $(
-            fn [<set_  $x>]<S: Storage<f64, Const<N>>>(
-                &mut self,
-                value: &Matrix<f64, Const<N>, Const<1>, S>,
-            );
+                /// Total over all stages of the compartment
+                #[allow(dead_code)]
+                fn [<get_  $x>](&self) -> OVector<f64, D> {
+                    (0..self.[<$x _stages>]())
+                        .map(|stage| self.[<get_ $x _stage>](stage))
+                        .fold(OVector::zeros_generic(self.d, U1), |acc, stage| acc + stage)
+                }
+
+                #[allow(dead_code)]
+                fn [<get_  $x _stage>](&self, stage: usize) -> MatrixView<'a, f64, D, U1, U1, Dyn> {
+                    self.y.generic_view((self.layout.start(${index()}, stage), 0), (self.d, U1))
+                }
+
+                #[allow(dead_code)]
+                fn [<$x _stages>](&self) -> usize {
+                    self.layout.stages[${index()}]
+                }
             )*
             }
         }
 
-        impl<const N: usize, S: StorageMut<f64, Const<{ ${count($x)} * N }>> + 'static> StateWrapperMut<N>
-        for Matrix<f64, Const<{ ${count($x)} * N }>, Const<1>, S>
-        {
+        /// Named, per-group setters into a model state
+        struct StateViewMut<'a, D: Dim> {
+            y: &'a mut State,
+            layout: &'a StateLayout,
+            d: D,
+        }
+
+        impl<'a, D: Dim> StateViewMut<'a, D> {
+            fn new(y: &'a mut State, layout: &'a StateLayout, d: D) -> Self {
+                Self { y, layout, d }
+            }
+
             paste! {
             $(
-                fn [<set_  $x>]<S2: Storage<f64, Const<N>>>(
+                /// Set the first stage of the compartment
+                #[allow(dead_code)]
+                fn [<set_  $x>]<S: Storage<f64, D>>(&mut self, value: &Matrix<f64, D, U1, S>) {
+                    self.[<set_ $x _stage>](0, value);
+                }
+
+                #[allow(dead_code)]
+                fn [<set_  $x _stage>]<S: Storage<f64, D>>(
                     &mut self,
-                    value: &Matrix<f64, Const<N>, Const<1>, S2>,
+                    stage: usize,
+                    value: &Matrix<f64, D, U1, S>,
                 ) {
-                    self.fixed_view_mut::<N, 1>(${index()} * N, 0).set_column(0, value);
+                    self.y
+                        .generic_view_mut((self.layout.start(${index()}, stage), 0), (self.d, U1))
+                        .copy_from(value);
                 }
             )*
-        }
+            }
         }
     }
 }
 
+// The latent and infectious periods are Erlang distributed, by splitting
+// the E and I compartments into a number of stages of equal mean duration.
+// Doses administered are also counted by the disease state of the recipients.
+// Vaccinated compartments hold one block of stages for each number of doses
+// received, so that dose `j` and stage `k` of EV is stage `j * k_E + k`.
+// Infectious people are split between symptomatic (I, IV) and asymptomatic
+// (IA, IVA) infections, which can be less infectious. People infected with
+// the variant, if any, go through their own copies of the compartments, from
+// the latent period to recovery, which are empty otherwise.
 make_state!(
-    s, e, i, r, sv, ev, iv, rv, y_cum, pre_h, h_cum, pre_d, d_cum
+    params;
+    s: 1,
+    sr: 1,
+    e: params.latent_stages,
+    i: params.infectious_stages,
+    ia: params.infectious_stages,
+    r: 1,
+    sv: params.mitigations.vaccine.doses,
+    svr: params.mitigations.vaccine.doses,
+    sv_ramp: params.mitigations.vaccine.doses,
+    svr_ramp: params.mitigations.vaccine.doses,
+    ev: params.mitigations.vaccine.doses * params.latent_stages,
+    iv: params.mitigations.vaccine.doses * params.infectious_stages,
+    iva: params.mitigations.vaccine.doses * params.infectious_stages,
+    rv: params.mitigations.vaccine.doses,
+    e_variant: params.variant.map_or(0, |_| params.latent_stages),
+    i_variant: params.variant.map_or(0, |_| params.infectious_stages),
+    ia_variant: params.variant.map_or(0, |_| params.infectious_stages),
+    r_variant: params.variant.map_or(0, |_| 1),
+    ev_variant: params.variant.map_or(0, |_| {
+        params.mitigations.vaccine.doses * params.latent_stages
+    }),
+    iv_variant: params.variant.map_or(0, |_| {
+        params.mitigations.vaccine.doses * params.infectious_stages
+    }),
+    iva_variant: params.variant.map_or(0, |_| {
+        params.mitigations.vaccine.doses * params.infectious_stages
+    }),
+    rv_variant: params.variant.map_or(0, |_| params.mitigations.vaccine.doses),
+    v_cum: params.mitigations.vaccine.doses,
+    v_state_cum: 4,
+    i_cum: 1,
+    iv_cum: params.mitigations.vaccine.doses,
+    variant_cum: params.variant.map_or(0, |_| 1),
+    reinf_cum: 1,
+    y_cum: 1,
+    pre_h: 1,
+    h_cum: 1,
+    hosp: 1,
+    icu: 1,
+    pre_d: 1,
+    d_cum: 1
 );
 
-impl<const N: usize> SEIRModel<N> {
-    pub fn new(parameters: Parameters<N>) -> Self {
-        let contact_matrix = parameters.contact_matrix;
-        let (eigenvalue, _) = get_dominant_eigendata(&contact_matrix);
+impl<D: Dim> GenericSEIRModel<D>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    pub fn new(parameters: GenericParameters<D>) -> Self {
+        let (d, _) = parameters.population_fractions.shape_generic();
+        let contact_matrix = parameters.total_contact_matrix();
+        // Normalize by the dominant eigenvalue of the next generation matrix
+        // of a fully susceptible population, up to the factor of R0
+        let (eigenvalue, _) = get_dominant_eigendata(&next_generation_matrix(
+            &parameters,
+            &contact_matrix,
+            &parameters.population_fractions,
+            &parameters.mean_infectiousness(),
+        ));
         let ave = AVE::new(&parameters);
-        SEIRModel {
+        let layout = StateLayout::new(&parameters);
+        let vaccine = &parameters.mitigations.vaccine;
+        let vaccine_schedule = VaccineSchedule::new(vaccine);
+        let efficacy: Vec<_> = (0..vaccine.doses)
+            .map(|dose| vaccine.efficacy(dose))
+            .collect();
+        let mut strains = vec![Strain {
+            slots: FIRST_STRAIN_SLOTS,
+            transmissibility: 1.0,
+            efficacy: efficacy.clone(),
+            infection_escape: 0.0,
+            introduction_day: 0,
+        }];
+        if let Some(variant) = parameters.variant {
+            strains.push(Strain {
+                slots: VARIANT_SLOTS,
+                transmissibility: variant.relative_transmissibility,
+                efficacy: efficacy
+                    .into_iter()
+                    .map(|efficacy| variant.efficacy(efficacy))
+                    .collect(),
+                infection_escape: variant.infection_escape,
+                introduction_day: variant.introduction_day,
+            });
+        }
+        GenericSEIRModel {
             parameters,
+            d,
+            layout,
+            contact_matrix,
             contact_matrix_normalization: eigenvalue,
             ave,
+            vaccine_schedule,
+            strains,
         }
     }
-}
 
-/// Probability of at least 1 success among N trials each with probability p
-pub fn p_detect1(n: f64, p: f64) -> f64 {
-    1.0 - (1.0 - p).powi(n as i32)
-}
+    /// State on the first day: everyone is susceptible but the initially
+    /// immune, vaccinated and infected, and the variant if it is introduced
+    /// on that day
+    fn initial_state(&self) -> State {
+        let params = &self.parameters;
+        let group_sizes = &params.population_fractions * params.population;
+        let mut initial_state = State::zeros(self.layout.len);
+        let mut initial = StateViewMut::new(&mut initial_state, &self.layout, self.d);
+        // Everyone starts out susceptible, apart from the initially immune,
+        // vaccinated and infected
+        let immune = group_sizes.component_mul(&params.initial_immunity);
+        let vaccinated = group_sizes.component_mul(&params.initial_vaccinated);
+        let initial_infections = params.initial_infections_by_group();
+        initial.set_s(&(&group_sizes - &immune - &vaccinated - &initial_infections));
+        initial.set_r(&immune);
+        initial.set_sv_stage(params.mitigations.vaccine.doses - 1, &vaccinated);
+        let fraction_symptomatic = &params.fraction_symptomatic;
+        initial.set_i(&initial_infections.component_mul(fraction_symptomatic));
+        initial.set_ia(&initial_infections.component_mul(&fraction_symptomatic.map(|fs| 1.0 - fs)));
+        self.introduce_variant(0, &mut initial_state);
+        initial_state
+    }
 
-impl<const N: usize> DynodeModel for SEIRModel<N>
-where
-    [(); 13 * N]: Sized,
-{
-    fn integrate(&self, days: usize) -> ModelOutput {
-        let population_fractions = self.parameters.population_fractions;
-        let mut initial_state: State<N> = SVector::zeros();
-        initial_state.set_s(
-            &(population_fractions
-                * (self.parameters.population - self.parameters.initial_infections)),
-        );
-        initial_state.set_i(&(population_fractions * self.parameters.initial_infections));
+    /// Infections with the variant introduced in each group at the start of
+    /// `day`, if it is the day of its introduction
+    fn variant_introduction(&self, day: usize) -> Option<OVector<f64, D>> {
+        self.parameters
+            .variant
+            .filter(|variant| variant.introduction_day == day)
+            .map(|variant| &self.parameters.population_fractions * variant.introduced_infections)
+    }
 
-        let mut stepper = Dopri5::new(self, 0.0, days as f64, 1.0, initial_state, 1e-6, 1e-6);
-        let _res = stepper.integrate();
+    /// Infect people with the variant introduced at the start of `day`
+    fn introduce_variant(&self, day: usize, state: &mut State) {
+        if let Some(infections) = self.variant_introduction(day) {
+            self.infect_with_variant(&infections, state);
+        }
+    }
 
-        let mut output = ModelOutput::new();
+    /// Move `infections` people of each group from the susceptible to the
+    /// latent period of the variant, as many as are still susceptible
+    fn infect_with_variant(&self, infections: &OVector<f64, D>, state: &mut State) {
+        let s = self.layout.s_slot(0);
+        let exposed = self.layout.e_variant_slot(0);
+        for (group, infections) in infections.iter().enumerate() {
+            let infections = infections.min(state[s + group]);
+            state[s + group] -= infections;
+            state[exposed + group] += infections;
+        }
+    }
 
-        let mut first_loop = true;
-        let mut prev_i_plus_r = SVector::zeros();
-        let mut prev_iv_plus_rv = SVector::zeros();
-        let mut prev_h_cum = SVector::zeros();
-        let mut prev_d_cum = SVector::zeros();
-
-        for (time, state) in stepper.x_out().iter().zip(stepper.y_out().iter()) {
-            let i_plus_r = state.get_i() + state.get_r();
-            let iv_plus_rv = state.get_iv() + state.get_rv();
-            if first_loop {
-                prev_i_plus_r = i_plus_r;
-                prev_iv_plus_rv = iv_plus_rv;
-                prev_h_cum = state.get_h_cum().into();
-                prev_d_cum = state.get_d_cum().into();
-                first_loop = false;
-            } else {
-                let new_infections_unvac = i_plus_r - prev_i_plus_r;
-                let new_infections_vac = iv_plus_rv - prev_iv_plus_rv;
-                let new_infections = new_infections_unvac + new_infections_vac;
-                let new_symptomatic = (new_infections_unvac
-                    + (1.0 - self.parameters.mitigations.vaccine.ve_p) * new_infections_vac)
-                    .component_mul(&self.parameters.fraction_symptomatic);
-                let new_hospitalizations = state.get_h_cum() - prev_h_cum;
-                let new_deaths = state.get_d_cum() - prev_d_cum;
-                output.add_infection_incidence(*time, new_infections.data.as_slice().into());
-                output.add_symptomatic_incidence(*time, new_symptomatic.data.as_slice().into());
-                output.add_hospital_incidence(*time, new_hospitalizations.data.as_slice().into());
-                output.add_death_incidence(*time, new_deaths.data.as_slice().into());
-                output.add_p_detect(
-                    *time,
-                    p_detect1(
-                        state.get_y_cum().sum() * self.parameters.p_test_sympto,
-                        self.parameters.test_sensitivity * self.parameters.p_test_forward,
-                    ),
-                );
-                prev_i_plus_r = i_plus_r;
-                prev_iv_plus_rv = iv_plus_rv;
-                prev_h_cum = state.get_h_cum().into();
-                prev_d_cum = state.get_d_cum().into();
+    /// Community mitigation periods that start on a given day are in effect
+    /// from the start, and the others are pending until triggered
+    fn initial_community_windows(&self) -> Vec<MitigationWindow> {
+        self.parameters
+            .mitigations
+            .community
+            .periods
+            .iter()
+            .map(|period| match period.trigger {
+                MitigationTrigger::Day => MitigationWindow::starting(period, period.start),
+                _ => MitigationWindow::PENDING,
+            })
+            .collect()
+    }
+
+    /// Daily outputs from the states at the start of each day, given the days
+    /// during which each community mitigation period was in effect
+    fn output(&self, times: &[f64], states: &[State], windows: &[MitigationWindow]) -> ModelOutput {
+        let d = self.d;
+        let mut output = ModelOutput::new();
+        if self.parameters.mitigations.community.enabled {
+            for (period, window) in windows.iter().enumerate() {
+                if window.start.is_finite() {
+                    output.add_community_mitigation(
+                        period,
+                        window.start,
+                        Some(window.end).filter(|end| end.is_finite()),
+                    );
+                }
             }
         }
+
+        for (time, (prev, state)) in times
+            .iter()
+            .skip(1)
+            .zip(states.iter().zip(states.iter().skip(1)))
+        {
+            let prev = StateView::new(prev, &self.layout, d);
+            let state = StateView::new(state, &self.layout, d);
+            let new_infections =
+                state.get_i_cum() - prev.get_i_cum() + state.get_iv_cum() - prev.get_iv_cum();
+            let new_variant_infections = state.get_variant_cum() - prev.get_variant_cum();
+            let new_reinfections = state.get_reinf_cum() - prev.get_reinf_cum();
+            // Counted as they happen, rather than as their expected share of
+            // infections, so that stochastic runs report actual cases
+            let new_symptomatic = state.get_y_cum() - prev.get_y_cum();
+            let new_hospitalizations = state.get_h_cum() - prev.get_h_cum();
+            let new_deaths = state.get_d_cum() - prev.get_d_cum();
+            let new_doses = state.get_v_cum() - prev.get_v_cum();
+            output.add_infection_incidence(*time, new_infections.iter().copied().collect());
+            output.add_first_strain_infection_incidence(
+                *time,
+                (&new_infections - &new_variant_infections)
+                    .iter()
+                    .copied()
+                    .collect(),
+            );
+            output.add_variant_infection_incidence(
+                *time,
+                new_variant_infections.iter().copied().collect(),
+            );
+            output.add_reinfection_incidence(*time, new_reinfections.iter().copied().collect());
+            output.add_symptomatic_incidence(*time, new_symptomatic.iter().copied().collect());
+            output.add_hospital_incidence(*time, new_hospitalizations.iter().copied().collect());
+            output.add_death_incidence(*time, new_deaths.iter().copied().collect());
+            // the hospital census includes patients in intensive care
+            let census = state.get_hosp() + state.get_icu();
+            output.add_hospital_census(*time, census.iter().copied().collect());
+            output.add_icu_census(*time, state.get_icu().iter().copied().collect());
+            output.add_doses_administered(*time, new_doses.iter().copied().collect());
+            output.add_doses_by_state(
+                *time,
+                std::array::from_fn(|k| {
+                    (state.get_v_state_cum_stage(k) - prev.get_v_state_cum_stage(k))
+                        .iter()
+                        .copied()
+                        .collect()
+                }),
+            );
+            output.add_vaccine_stockpile(
+                *time,
+                self.vaccine_schedule.delivered(*time) - state.get_v_cum().sum(),
+            );
+            output.add_p_detect(*time, self.p_detect(state.get_y_cum().sum()));
+            // Transmission grows with the strain that spreads the most, among
+            // those introduced
+            let rt = self
+                .strains
+                .iter()
+                .filter(|strain| strain.introduction_day as f64 <= *time)
+                .map(|strain| self.effective_reproduction_number(*time, &state, windows, strain))
+                .fold(0.0, f64::max);
+            output.add_rt(*time, rt);
+        }
         output
     }
-}
 
-impl<const N: usize> System<f64, State<N>> for &SEIRModel<N> {
-    fn system(&self, x: f64, y: &State<N>, dy: &mut State<N>) {
-        let s = y.get_s();
-        let e = y.get_e();
-        let i = y.get_i();
-        let r = y.get_r();
-        let sv = y.get_sv();
-        let ev = y.get_ev();
-        let iv = y.get_iv();
-        let pre_h = y.get_pre_h();
-        let pre_d = y.get_pre_d();
+    /// Probability that the epidemic has been detected, given the cumulative
+    /// number of symptomatic infections
+    fn p_detect(&self, symptomatic: f64) -> f64 {
+        p_detect1(
+            symptomatic * self.parameters.p_test_sympto,
+            self.parameters.test_sensitivity * self.parameters.p_test_forward,
+        )
+    }
 
-        let params = &self.parameters;
-        let community_params = &params.mitigations.community;
+    /// Put in effect or lift the community mitigation periods whose trigger
+    /// or release is met on the day ending at `time`, given the states at the
+    /// start and end of the day. Returns whether any period changed.
+    fn update_community_windows(
+        &self,
+        windows: &mut [MitigationWindow],
+        time: f64,
+        prev: &State,
+        state: &State,
+    ) -> bool {
+        let community = &self.parameters.mitigations.community;
+        if !community.enabled {
+            return false;
+        }
+        let prev = StateView::new(prev, &self.layout, self.d);
+        let state = StateView::new(state, &self.layout, self.d);
+        let admissions = (state.get_h_cum().sum() - prev.get_h_cum().sum())
+            / self.parameters.population
+            * 100_000.0;
+        let p_detect = self.p_detect(state.get_y_cum().sum());
 
-        let ve_s = self.parameters.mitigations.vaccine.ve_s;
-        let ve_i = self.parameters.mitigations.vaccine.ve_i;
-        let ve_p = self.parameters.mitigations.vaccine.ve_p;
+        let mut changed = false;
+        for (period, window) in community.periods.iter().zip(windows.iter_mut()) {
+            if window.start.is_infinite() {
+                let triggered = time >= period.start
+                    && match period.trigger {
+                        MitigationTrigger::Day => false,
+                        MitigationTrigger::HospitalAdmissions(threshold) => admissions >= threshold,
+                        MitigationTrigger::Detection(threshold) => p_detect >= threshold,
+                    };
+                if triggered {
+                    *window = MitigationWindow::starting(period, time);
+                    changed = true;
+                }
+            } else if window.end.is_infinite() && time > window.start {
+                let released = match period.release {
+                    MitigationRelease::Duration => false,
+                    MitigationRelease::HospitalAdmissions(threshold) => admissions < threshold,
+                };
+                if released {
+                    window.end = time;
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
 
-        // Community mitigation
-        let contact_matrix = if community_params.enabled
-            && x >= community_params.start
-            && x < (community_params.start + community_params.duration)
-        {
-            self.parameters.contact_matrix.component_mul(
-                &(SMatrix::<f64, N, N>::from_element(1.0) - community_params.effectiveness),
-            ) / self.contact_matrix_normalization
+    /// Normalized contacts on day `t`, given the days during which each
+    /// community mitigation period is in effect
+    fn contact_matrix_at(&self, t: f64, windows: &[MitigationWindow]) -> OMatrix<f64, D, D> {
+        let community_params = &self.parameters.mitigations.community;
+        let active_periods: Vec<_> = community_params
+            .periods
+            .iter()
+            .zip(windows)
+            .filter(|(_, window)| community_params.enabled && window.contains(t))
+            .map(|(period, _)| period)
+            .collect();
+        if active_periods.is_empty() {
+            &self.contact_matrix / self.contact_matrix_normalization
         } else {
-            self.parameters.contact_matrix / self.contact_matrix_normalization
+            self.mitigated_contact_matrix(&active_periods) / self.contact_matrix_normalization
+        }
+    }
+
+    /// Effective reproduction number of a strain on day `t`: dominant
+    /// eigenvalue of the next generation matrix given the people left
+    /// susceptible to it, their protection by vaccines, and the mitigations
+    /// in effect. Infections in each group are as infectious as the mix of
+    /// vaccine statuses among the people who would be infected.
+    fn effective_reproduction_number(
+        &self,
+        t: f64,
+        y: &StateView<D>,
+        windows: &[MitigationWindow],
+        strain: &Strain,
+    ) -> f64 {
+        let params = &self.parameters;
+        let vaccine_params = &params.mitigations.vaccine;
+        let rr_i = &self.ave.rr_i;
+        // Infectiousness relative to an untreated symptomatic infection
+        let infectiousness = |(_, ve_i, ve_p): (f64, f64, f64)| {
+            params.fraction_symptomatic.zip_map(rr_i, |fs, rr_i| {
+                let symptomatic = fs * (1.0 - ve_p);
+                (1.0 - ve_i)
+                    * (symptomatic * rr_i
+                        + (1.0 - symptomatic) * params.asymptomatic_relative_infectiousness)
+            })
+        };
+        let unprotected = (0.0, 0.0, 0.0);
+        // People recovered from the first strain are susceptible to the
+        // protection that the strain escapes
+        let escaped = strain.infection_escape;
+        // Susceptible people, weighted by their protection against infection,
+        // and the infectiousness of the infections they would get
+        let (susceptible, weighted) = (0..vaccine_params.doses).fold(
+            {
+                let unvaccinated = y.get_s() + y.get_sr() + y.get_r() * escaped;
+                let weighted = unvaccinated.component_mul(&infectiousness(unprotected));
+                (unvaccinated, weighted)
+            },
+            |(susceptible, weighted), dose| {
+                let efficacy = strain.efficacy[dose];
+                // People ramping up are protected by their previous dose
+                let ramp_efficacy = match dose {
+                    0 => unprotected,
+                    _ => strain.efficacy[dose - 1],
+                };
+                let vaccinated =
+                    (y.get_sv_stage(dose) + y.get_svr_stage(dose) + y.get_rv_stage(dose) * escaped)
+                        * (1.0 - efficacy.0);
+                let ramping = (y.get_sv_ramp_stage(dose) + y.get_svr_ramp_stage(dose))
+                    * (1.0 - ramp_efficacy.0);
+                (
+                    susceptible + &vaccinated + &ramping,
+                    weighted
+                        + vaccinated.component_mul(&infectiousness(efficacy))
+                        + ramping.component_mul(&infectiousness(ramp_efficacy)),
+                )
+            },
+        );
+        let infectiousness = weighted.zip_map(&susceptible, |weighted, susceptible| {
+            if susceptible > 0.0 {
+                weighted / susceptible
+            } else {
+                0.0
+            }
+        });
+        let next_generation =
+            next_generation_matrix(
+                params,
+                &self.contact_matrix_at(t, windows),
+                &(susceptible / params.population),
+                &infectiousness,
+            ) * (params.r0 * strain.transmissibility * params.transmission_multiplier(t));
+        let (rt, _) = get_dominant_eigendata(&next_generation);
+        rt
+    }
+
+    /// Contacts while the given community mitigation periods are in effect.
+    /// Reductions in each setting, and across pairs of groups, compose
+    /// multiplicatively between periods.
+    fn mitigated_contact_matrix(
+        &self,
+        periods: &[&GenericCommunityMitigationPeriod<D>],
+    ) -> OMatrix<f64, D, D> {
+        let parameters = &self.parameters;
+        let setting_multiplier = |setting| {
+            periods
+                .iter()
+                .flat_map(|period| &period.setting_effectiveness)
+                .filter(|reduction| reduction.setting == setting)
+                .map(|reduction| 1.0 - reduction.effectiveness)
+                .product::<f64>()
         };
+        let contacts = parameters.setting_contacts.iter().fold(
+            parameters.contact_matrix.clone(),
+            |total, contacts| {
+                total + &contacts.contact_matrix * setting_multiplier(contacts.setting)
+            },
+        );
+        periods.iter().fold(contacts, |contacts, period| {
+            contacts.component_mul(&period.effectiveness.map(|eff| 1.0 - eff))
+        })
+    }
 
-        // Transmission
-        let beta = self.parameters.r0 / self.parameters.infectious_period;
-        let ones = SVector::<f64, N>::from_element(1.0);
-        let i_effective = i.component_mul(&self.ave.rr_i)
-            + (iv * (1.0 - ve_i)).component_mul(&(ones + (1.0 - ve_p) * (ones - self.ave.rr_i)));
+    /// Share of first doses going to each group, given the number of first
+    /// doses each group has received and the number of people who can receive
+    /// one. Groups that reached their coverage cap are left out.
+    fn first_dose_shares(
+        &self,
+        vaccinated: &OVector<f64, D>,
+        recipients: &OVector<f64, D>,
+    ) -> OVector<f64, D> {
+        let vaccine = &self.parameters.mitigations.vaccine;
+        let population_fractions = &self.parameters.population_fractions;
+        // People initially vaccinated count towards the coverage cap
+        let open = |group: usize| {
+            vaccinated[group]
+                < (vaccine.coverage_cap(group) - self.parameters.initial_vaccinated[group])
+                    * population_fractions[group]
+                    * self.parameters.population
+                && recipients[group] > MIN_RECIPIENTS
+        };
+        let weights = match &vaccine.allocation {
+            VaccineAllocation::ProRata => population_fractions.clone(),
+            VaccineAllocation::Priority(order) => {
+                let mut weights = OVector::zeros_generic(self.d, U1);
+                if let Some(&group) = order.iter().find(|&&group| open(group)) {
+                    weights[group] = 1.0;
+                }
+                weights
+            }
+            VaccineAllocation::Shares(shares) => {
+                OVector::from_iterator_generic(self.d, U1, shares.iter().copied())
+            }
+        };
+        normalize(OVector::from_fn_generic(self.d, U1, |group, _| {
+            if open(group) { weights[group] } else { 0.0 }
+        }))
+    }
+}
 
-        let infection_rate = (beta / self.parameters.population)
-            * (contact_matrix * i_effective).component_div(&self.parameters.population_fractions);
+/// Scale non-negative weights to sum to one, or leave them all zero
+fn normalize<D: Dim>(weights: OVector<f64, D>) -> OVector<f64, D>
+where
+    DefaultAllocator: Allocator<D>,
+{
+    let total = weights.sum();
+    if total > 0.0 {
+        weights / total
+    } else {
+        weights
+    }
+}
 
-        let ds_to_e = s.component_mul(&infection_rate);
-        let de_to_i = e / self.parameters.latent_period;
-        let di_to_r = i / self.parameters.infectious_period;
+/// Days during which a community mitigation period is in effect. Periods that
+/// have not been triggered, or lifted, yet have infinite bounds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MitigationWindow {
+    start: f64,
+    end: f64,
+}
 
-        let dsv_to_ev = sv.component_mul(&((1.0 - ve_s) * infection_rate));
-        let dev_to_iv = ev / self.parameters.latent_period;
-        let div_to_rv = iv / self.parameters.infectious_period;
+impl MitigationWindow {
+    const PENDING: Self = MitigationWindow {
+        start: f64::INFINITY,
+        end: f64::INFINITY,
+    };
 
-        // Vaccine
-        let administration_rate = if self.parameters.mitigations.vaccine.enabled {
-            if x < self.parameters.mitigations.vaccine.start {
-                0.0
-            } else if (x - self.parameters.mitigations.vaccine.start)
-                * self.parameters.mitigations.vaccine.administration_rate
-                <= self.parameters.mitigations.vaccine.doses_available
-            {
-                self.parameters.mitigations.vaccine.administration_rate
-            } else {
-                0.0
+    fn starting<D: Dim>(period: &GenericCommunityMitigationPeriod<D>, start: f64) -> Self
+    where
+        DefaultAllocator: Allocator<D, D>,
+    {
+        let end = match period.release {
+            MitigationRelease::Duration => start + period.duration,
+            MitigationRelease::HospitalAdmissions(_) => f64::INFINITY,
+        };
+        MitigationWindow { start, end }
+    }
+
+    fn contains(&self, t: f64) -> bool {
+        t >= self.start && t < self.end
+    }
+}
+
+/// Probability of at least 1 success among N trials each with probability p
+pub fn p_detect1(n: f64, p: f64) -> f64 {
+    1.0 - (1.0 - p).powi(n as i32)
+}
+
+impl<D: Dim> DynodeModel for GenericSEIRModel<D>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    fn integrate(&self, days: usize) -> ModelOutput {
+        // Integrate until the first day on which a community mitigation is
+        // triggered or lifted, or the variant is introduced, and carry on
+        // from that day with the updated mitigations or the introduced
+        // infections
+        let mut windows = self.initial_community_windows();
+        let mut times = vec![0.0];
+        let mut states = vec![self.initial_state()];
+        loop {
+            let start_day = times.len() - 1;
+            let system = SEIRSystem {
+                model: self,
+                community_windows: &windows,
+            };
+            let (new_times, new_states) = solve(system, start_day, days, states[start_day].clone());
+            times.extend(new_times.into_iter().skip(1));
+            states.extend(new_states.into_iter().skip(1));
+            let event = (start_day + 1..times.len()).find(|&day| {
+                let changed = self.update_community_windows(
+                    &mut windows,
+                    times[day],
+                    &states[day - 1],
+                    &states[day],
+                );
+                changed || self.variant_introduction(day).is_some()
+            });
+            match event {
+                Some(day) => {
+                    times.truncate(day + 1);
+                    states.truncate(day + 1);
+                    self.introduce_variant(day, &mut states[day]);
+                }
+                None => break,
             }
-        } else {
-            0.0
+        }
+        self.output(&times, &states, &windows)
+    }
+
+    fn reproduction_numbers(&self) -> ReproductionNumbers {
+        let next_generation = next_generation_matrix(
+            &self.parameters,
+            &self.contact_matrix,
+            &self.parameters.population_fractions,
+            &self.parameters.mean_infectiousness(),
+        ) * (self.parameters.r0
+            * self.parameters.transmission_multiplier(0.0)
+            / self.contact_matrix_normalization);
+        let (r0, _) = get_dominant_eigendata(&next_generation);
+        ReproductionNumbers {
+            r0,
+            by_group: next_generation
+                .column_iter()
+                .map(|column| column.sum())
+                .collect(),
+        }
+    }
+
+    fn introductions(&self) -> Introductions {
+        let params = &self.parameters;
+        let (d, _) = params.population_fractions.shape_generic();
+        let ones = OVector::<f64, D>::from_element_generic(d, U1, 1.0);
+        // Infections caused by a symptomatic infection in each group over its
+        // infectious period, at the rate of K_ij / T_I
+        let next_generation = next_generation_matrix(
+            params,
+            &self.contact_matrix,
+            &params.population_fractions,
+            &ones,
+        ) * (params.r0 * params.transmission_multiplier(0.0)
+            / self.contact_matrix_normalization);
+        // Generating function of the number of infections caused in each
+        // group, which is Poisson given the infectious period, itself Erlang
+        // distributed with k_I stages
+        let stages = params.infectious_stages as f64;
+        let rho_a = params.asymptomatic_relative_infectiousness;
+        let generating_function = |q: &OVector<f64, D>| {
+            let escaping = next_generation.tr_mul(&(&ones - q));
+            escaping.zip_map(&params.fraction_symptomatic, |escaping, fs| {
+                fs * (1.0 + escaping / stages).powf(-stages)
+                    + (1.0 - fs) * (1.0 + rho_a * escaping / stages).powf(-stages)
+            })
         };
-        // 0.5828430256204575
-        let ds_to_sv = s
-            .component_div(&(s + e + i + r))
-            .component_mul(&self.parameters.population_fractions)
-            * administration_rate;
+        // The extinction probabilities are the smallest fixed point, which
+        // iterating from zero converges to
+        let mut p_extinction = OVector::<f64, D>::zeros_generic(d, U1);
+        for _ in 0..MAX_EXTINCTION_ITERATIONS {
+            let next = generating_function(&p_extinction);
+            let converged = (&next - &p_extinction).amax() <= EXTINCTION_TOLERANCE;
+            p_extinction = next;
+            if converged {
+                break;
+            }
+        }
 
-        // Symptomatic
-        // at risk of progression to symptoms
-        let dat_risk = de_to_i + dev_to_iv * (1.0 - ve_p);
-        // progression to symptoms
-        let dsymp = dat_risk.component_mul(&self.parameters.fraction_symptomatic);
+        // Lineages that survive grow at the growth rate, from the
+        // reproductive value of the introduced infection, over their
+        // probability of surviving. Transmission is sustained once they reach
+        // enough infections, spread over the groups as in a growing epidemic,
+        // for all of them to die out with less than `ESTABLISHMENT_RISK`.
+        let mean_next_generation =
+            &next_generation * OMatrix::from_diagonal(&params.mean_infectiousness());
+        let (_, mix) = get_dominant_eigendata(&mean_next_generation);
+        let (_, reproductive_value) = get_dominant_eigendata(&mean_next_generation.transpose());
+        let reproductive_value = &reproductive_value / reproductive_value.dot(&mix);
+        let p_mix_extinction = p_extinction.dot(&mix);
+        let growth_rate = params.growth_rate();
+        let days_to_establishment = p_extinction
+            .iter()
+            .zip(reproductive_value.iter())
+            .map(|(&p_extinction, &reproductive_value)| {
+                if growth_rate <= 0.0 || p_extinction >= 1.0 - EXTINCTION_TOLERANCE {
+                    return f64::INFINITY;
+                }
+                let established = if p_mix_extinction > 0.0 {
+                    (ESTABLISHMENT_RISK.ln() / p_mix_extinction.ln()).max(1.0)
+                } else {
+                    1.0
+                };
+                let expected = reproductive_value / (1.0 - p_extinction);
+                ((established / expected).ln() / growth_rate).max(0.0)
+            })
+            .collect();
+        Introductions {
+            p_extinction: p_extinction.iter().copied().collect(),
+            days_to_establishment,
+        }
+    }
+}
 
-        // Hospitalizations
-        let dto_pre_h = dat_risk
-            .component_mul(&self.parameters.fraction_hospitalized)
-            .component_mul(&self.ave.rr_p_hosp);
-        let dpre_h_to_h_cum = pre_h / self.parameters.hospitalization_delay;
+// Next generation matrix, up to the factor of beta: relative number of people
+// infected in group i by an infection in group j over its infectious period,
+// given the number of susceptible people in each group as a fraction of the
+// population, and the infectiousness of infections in each group relative to
+// a symptomatic infection. The force of infection on group i is spread over
+// its members, so the population fractions cancel out in a fully susceptible
+// population.
+fn next_generation_matrix<D: Dim>(
+    parameters: &GenericParameters<D>,
+    contact_matrix: &OMatrix<f64, D, D>,
+    susceptible: &OVector<f64, D>,
+    infectiousness: &OVector<f64, D>,
+) -> OMatrix<f64, D, D>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    let susceptibility = susceptible
+        .component_div(&parameters.population_fractions)
+        .component_mul(&parameters.relative_susceptibility);
+    let infectiousness = parameters
+        .relative_infectiousness
+        .component_mul(infectiousness);
+    OMatrix::from_diagonal(&susceptibility)
+        * contact_matrix
+        * OMatrix::from_diagonal(&infectiousness)
+}
 
-        // Deaths
-        let dto_pre_d = dat_risk
-            .component_mul(&self.parameters.fraction_dead)
-            .component_mul(&self.ave.rr_p_death);
-
-        let dpre_d_to_d_cum = pre_d / self.parameters.death_delay;
-
-        // Collect derivatives
-        dy.set_s(&-(ds_to_e + ds_to_sv));
-        dy.set_e(&(ds_to_e - de_to_i));
-        dy.set_i(&(de_to_i - di_to_r));
-        dy.set_r(&di_to_r);
-        dy.set_sv(&(-dsv_to_ev + ds_to_sv));
-        dy.set_ev(&(dsv_to_ev - dev_to_iv));
-        dy.set_iv(&(dev_to_iv - div_to_rv));
-        dy.set_rv(&div_to_rv);
-        dy.set_y_cum(&dsymp);
-        dy.set_pre_h(&(dto_pre_h - dpre_h_to_h_cum));
-        dy.set_h_cum(&dpre_h_to_h_cum);
-        dy.set_pre_d(&(dto_pre_d - dpre_d_to_d_cum));
-        dy.set_d_cum(&dpre_d_to_d_cum);
+// Integrate a system from `start_day` until `days`, reporting the state at
+// every day. This is kept free of allocator bounds on the group dimension,
+// which would otherwise get in the way of inferring the solver's state
+// dimension.
+fn solve<F: System<f64, State>>(
+    system: F,
+    start_day: usize,
+    days: usize,
+    initial_state: State,
+) -> (Vec<f64>, Vec<State>) {
+    let x = start_day as f64;
+    let x_end = days as f64;
+    // Solver defaults, except that the stiffness test is turned off: once an
+    // epidemic has burnt out, long runs are limited by stability rather than
+    // accuracy, which the solver would otherwise report as an error and stop.
+    let mut stepper = Dopri5::from_param(
+        system,
+        x,
+        x_end,
+        1.0,
+        initial_state,
+        RTOL,
+        ATOL,
+        SAFETY_FACTOR,
+        BETA,
+        FAC_MIN,
+        FAC_MAX,
+        x_end - x,
+        INITIAL_STEP,
+        MAX_STEPS,
+        STIFFNESS_TEST_INTERVAL,
+        SolverOutputType::Dense,
+    );
+    let _res = stepper.integrate();
+    (stepper.x_out().clone(), stepper.y_out().clone())
+}
+
+/// The model, along with the days during which each community mitigation
+/// period is in effect
+struct SEIRSystem<'a, D: Dim>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    model: &'a GenericSEIRModel<D>,
+    community_windows: &'a [MitigationWindow],
+}
+
+/// Receiver of the flows of people between compartments, per group per day,
+/// as they are computed. Compartments are given by the position of their
+/// first group in the state. Flows without a source (`from` is `None`) only
+/// add to their destinations, such as the counters of doses given to people
+/// who stay in their disease state.
+trait Flows<D: Dim>
+where
+    DefaultAllocator: Allocator<D>,
+{
+    /// Flow out of `from` and into each of `to`, where each person moved
+    /// also adds to `outcome` with probability `fraction` for each
+    /// `(outcome, fraction)` of `outcomes`, such as the people who will be
+    /// hospitalized or die after infection
+    fn flow_with_outcomes(
+        &mut self,
+        from: Option<usize>,
+        to: &[usize],
+        rate: OVector<f64, D>,
+        outcomes: &[(usize, &OVector<f64, D>)],
+    );
+
+    fn flow(&mut self, from: Option<usize>, to: &[usize], rate: OVector<f64, D>) {
+        self.flow_with_outcomes(from, to, rate, &[]);
     }
 }
 
-// Compute dominant eigenvalue and eigenvector using power algorithm
-fn get_dominant_eigendata<const N: usize, S: Storage<f64, Const<N>, Const<N>>>(
-    matrix: &Matrix<f64, Const<N>, Const<N>, S>,
-) -> (f64, SVector<f64, N>) {
-    let mut x = SVector::<f64, N>::from_element(1.0 / N as f64);
-    let mut norm = 1.0 as f64;
-    loop {
-        x = matrix * x;
-        let new_norm = x.lp_norm(1);
-        x = x / new_norm;
-        if (new_norm - norm).abs() < f64::EPSILON {
-            return (norm, x);
-        } else {
-            norm = new_norm;
+/// Derivative of the state, adding up the flows
+struct Derivative<'a>(&'a mut State);
+
+impl<D: Dim> Flows<D> for Derivative<'_>
+where
+    DefaultAllocator: Allocator<D>,
+{
+    fn flow_with_outcomes(
+        &mut self,
+        from: Option<usize>,
+        to: &[usize],
+        rate: OVector<f64, D>,
+        outcomes: &[(usize, &OVector<f64, D>)],
+    ) {
+        for (group, rate) in rate.iter().enumerate() {
+            if let Some(from) = from {
+                self.0[from + group] -= rate;
+            }
+            for to in to {
+                self.0[to + group] += rate;
+            }
+            for (outcome, fraction) in outcomes {
+                self.0[outcome + group] += rate * fraction[group];
+            }
         }
     }
 }
 
-#[cfg(test)]
-mod test {
-    use float_eq::assert_float_eq;
-    use nalgebra::{DVector, Matrix1, Vector1, matrix};
+impl<D: Dim> SEIRSystem<'_, D>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    /// Flows between compartments on day `x` in state `state`, always in
+    /// the same order. The ODE integrates them as rates, and the stochastic
+    /// model draws them as events.
+    fn transitions(&self, x: f64, state: &State, flows: &mut impl Flows<D>) {
+        let d = self.model.d;
+        let layout = &self.model.layout;
+        let y = StateView::new(state, layout, d);
+        let s = y.get_s();
+        let sr = y.get_sr();
+        let pre_h = y.get_pre_h();
+        let hosp = y.get_hosp();
+        let icu = y.get_icu();
+        let pre_d = y.get_pre_d();
 
-    use super::SEIRModel;
-    use crate::{
-        AntiviralsParams, DynodeModel, MitigationParams, ModelOutput, OutputType, Parameters,
-        VaccineParams, model::get_dominant_eigendata,
-    };
+        let params = &self.model.parameters;
+        let vaccine_params = &params.mitigations.vaccine;
+        let doses = vaccine_params.doses;
+        let latent_stages = params.latent_stages;
+        let infectious_stages = params.infectious_stages;
+        let strains = &self.model.strains;
+        let zeros = OVector::<f64, D>::zeros_generic(d, U1);
+        let stage = |slot: usize| state.generic_view((slot, 0), (d, U1)).clone_owned();
+        // Total over the `stages` stages of a compartment from `slot` on
+        let total = |slot: usize, stages: usize| {
+            (0..stages).fold(zeros.clone(), |acc, k| acc + stage(slot + k * layout.n))
+        };
 
-    #[derive(Debug)]
-    #[allow(dead_code)]
-    struct TestResults<const N: usize> {
-        pub attack_rate: f64,
+        // Vaccinated compartments, by number of doses received
+        let sv: Vec<_> = (0..doses)
+            .map(|dose| y.get_sv_stage(dose).into_owned())
+            .collect();
+        let svr: Vec<_> = (0..doses)
+            .map(|dose| y.get_svr_stage(dose).into_owned())
+            .collect();
+        // People who received a dose but are still ramping up to its protection
+        let ramp: Vec<_> = (0..doses)
+            .map(|dose| (y.get_sv_ramp_stage(dose), y.get_svr_ramp_stage(dose)))
+            .collect();
+        let v_cum: Vec<_> = (0..doses).map(|dose| y.get_v_cum_stage(dose)).collect();
+
+        // Community mitigation
+        let contact_matrix = self.model.contact_matrix_at(x, self.community_windows);
+
+        // Transmission
+        let beta = self.model.parameters.r0 / self.model.parameters.infectious_period
+            * self.model.parameters.transmission_multiplier(x);
+        let rho_a = params.asymptomatic_relative_infectiousness;
+
+        // Each stage lasts a 1/k fraction of the mean period
+        let latent_rate = latent_stages as f64 / params.latent_period;
+        let infectious_rate = infectious_stages as f64 / params.infectious_period;
+        // Infections become symptomatic in a `fraction_symptomatic` of
+        // unvaccinated people, reduced by `ve_p` in vaccinated people
+        let fraction_symptomatic = &params.fraction_symptomatic;
+
+        // Vaccine
+        let (vaccinate_infected, vaccinate_recovered) = match vaccine_params.targeting {
+            VaccineTargeting::All => (true, true),
+            VaccineTargeting::Seronegative => (true, false),
+            VaccineTargeting::Susceptible => (false, false),
+        };
+        // People exposed to, infectious with and recovered from any strain,
+        // without any dose and then by the number of doses received
+        let infected: Vec<[OVector<f64, D>; 3]> = (0..=doses)
+            .map(|received| {
+                strains.iter().fold(
+                    [zeros.clone(), zeros.clone(), zeros.clone()],
+                    |[exposed, infectious, recovered], strain| {
+                        let slots = &strain.slots;
+                        let (e, i, ia, r) = match received {
+                            0 => (
+                                (slots.e)(layout, 0),
+                                (slots.i)(layout, 0),
+                                (slots.ia)(layout, 0),
+                                (slots.r)(layout, 0),
+                            ),
+                            _ => (
+                                (slots.ev)(layout, (received - 1) * latent_stages),
+                                (slots.iv)(layout, (received - 1) * infectious_stages),
+                                (slots.iva)(layout, (received - 1) * infectious_stages),
+                                (slots.rv)(layout, received - 1),
+                            ),
+                        };
+                        [
+                            exposed + total(e, latent_stages),
+                            infectious + total(i, infectious_stages) + total(ia, infectious_stages),
+                            recovered + stage(r),
+                        ]
+                    },
+                )
+            })
+            .collect();
+        // People who can receive each dose by disease state (susceptible,
+        // exposed, infectious and recovered): those without any dose for first
+        // doses, and those whose last dose was the previous one for follow-up
+        // doses, unless their disease state is not targeted
+        let recipients: Vec<[OVector<f64, D>; 4]> = (0..doses)
+            .map(|dose| {
+                let susceptible = if dose == 0 {
+                    &s + &sr
+                } else {
+                    let (sv_ramp, svr_ramp) = &ramp[dose - 1];
+                    &sv[dose - 1] + &svr[dose - 1] + sv_ramp + svr_ramp
+                };
+                let [exposed, infectious, recovered] = infected[dose].clone();
+                [
+                    susceptible,
+                    if vaccinate_infected {
+                        exposed
+                    } else {
+                        zeros.clone()
+                    },
+                    if vaccinate_infected {
+                        infectious
+                    } else {
+                        zeros.clone()
+                    },
+                    if vaccinate_recovered {
+                        recovered
+                    } else {
+                        zeros.clone()
+                    },
+                ]
+            })
+            .collect();
+        let total_recipients: Vec<_> = recipients
+            .iter()
+            .map(|recipients| recipients.iter().fold(zeros.clone(), |acc, x| acc + x))
+            .collect();
+        // Doses administered to each group, by dose number. Follow-up doses go
+        // to the groups of the people who can receive them.
+        let dv_cum: Vec<_> = (0..doses)
+            .map(|dose| {
+                let shares = if dose == 0 {
+                    self.model
+                        .first_dose_shares(&v_cum[0].into_owned(), &total_recipients[0])
+                } else {
+                    normalize(total_recipients[dose].map(|recipients| {
+                        if recipients > MIN_RECIPIENTS {
+                            recipients
+                        } else {
+                            0.0
+                        }
+                    }))
+                };
+                shares * self.model.vaccine_schedule.rate(x, dose)
+            })
+            .collect();
+        // Doses are spread evenly over the people who can receive them
+        let per_capita: Vec<_> = dv_cum
+            .iter()
+            .zip(&total_recipients)
+            .map(|(doses, recipients)| {
+                doses.zip_map(recipients, |doses, recipients| {
+                    if recipients > MIN_RECIPIENTS {
+                        doses / recipients
+                    } else {
+                        0.0
+                    }
+                })
+            })
+            .collect();
+
+        // Every stage of an Erlang-distributed compartment but the last moves
+        // on to the next
+        let progress =
+            |flows: &mut dyn Flows<D>, slot: &dyn Fn(usize) -> usize, stages: usize, rate: f64| {
+                for k in 1..stages {
+                    flows.flow(Some(slot(k - 1)), &[slot(k)], stage(slot(k - 1)) * rate);
+                }
+            };
+        let reinf_cum = layout.reinf_cum_slot(0);
+        let i_cum = layout.i_cum_slot(0);
+        let y_cum = layout.y_cum_slot(0);
+        // Infections with the first strain imported from outside the
+        // population, such as residents infected while traveling. They are
+        // taken from the unvaccinated susceptible people, so that the
+        // population stays the same and fewer are imported as immunity
+        // builds up.
+        let imported = params.importations_at(x);
+        let group_sizes = &params.population_fractions * params.population;
+        let imported_from = |people: &OVector<f64, D>| {
+            people.zip_zip_map(&group_sizes, &imported, |people, size, imported| {
+                if size > 0.0 {
+                    imported * people / size
+                } else {
+                    0.0
+                }
+            })
+        };
+        flows.flow(
+            Some(layout.s_slot(0)),
+            &[layout.e_slot(0)],
+            imported_from(&s),
+        );
+        flows.flow(
+            Some(layout.sr_slot(0)),
+            &[layout.e_slot(0), reinf_cum],
+            imported_from(&sr),
+        );
+        // Each infection that progresses to the infectious period is at risk
+        // of hospitalization and death, less so for vaccinated people
+        let p_hosp = params
+            .fraction_hospitalized
+            .component_mul(&self.model.ave.rr_p_hosp);
+        let p_death = params
+            .fraction_dead
+            .component_mul(&self.model.ave.rr_p_death);
+        let (hospitalized, dead) = (layout.pre_h_slot(0), layout.pre_d_slot(0));
+        for strain in strains {
+            let slots = &strain.slots;
+            let e = |k| (slots.e)(layout, k);
+            let i = |k| (slots.i)(layout, k);
+            let ia = |k| (slots.ia)(layout, k);
+            let ev = |dose: usize, k| (slots.ev)(layout, dose * latent_stages + k);
+            let iv = |dose: usize, k| (slots.iv)(layout, dose * infectious_stages + k);
+            let iva = |dose: usize, k| (slots.iva)(layout, dose * infectious_stages + k);
+            // Infections with the strain are also counted on their own
+            let counted = |to: &[usize]| -> Vec<usize> {
+                to.iter()
+                    .copied()
+                    .chain(slots.cum.map(|cum| cum(layout, 0)))
+                    .collect()
+            };
+            let efficacy = &strain.efficacy;
+
+            let i_effective = efficacy.iter().enumerate().fold(
+                total(i(0), infectious_stages).component_mul(&self.model.ave.rr_i)
+                    + total(ia(0), infectious_stages) * rho_a,
+                |i_effective, (dose, (_, ve_i, _))| {
+                    let iv = total(iv(dose, 0), infectious_stages);
+                    let iva = total(iva(dose, 0), infectious_stages);
+                    i_effective
+                        + (iv.component_mul(&self.model.ave.rr_i) + iva * rho_a) * (1.0 - ve_i)
+                },
+            );
+            let infection_rate = (beta * strain.transmissibility
+                / self.model.parameters.population)
+                * (&contact_matrix * i_effective.component_mul(&params.relative_infectiousness))
+                    .component_mul(&params.relative_susceptibility)
+                    .component_div(&self.model.parameters.population_fractions);
+            // Infections of the people in `slot`, given their protection
+            let infections = |slot: usize, protection: f64| {
+                stage(slot).component_mul(&((1.0 - protection) * &infection_rate))
+            };
+
+            // Unvaccinated
+            let e0 = e(0);
+            flows.flow(
+                Some(layout.s_slot(0)),
+                &[e0],
+                infections(layout.s_slot(0), 0.0),
+            );
+            flows.flow(
+                Some(layout.sr_slot(0)),
+                &[e0, reinf_cum],
+                infections(layout.sr_slot(0), 0.0),
+            );
+            // People recovered from the first strain lose the protection that
+            // the strain escapes
+            let escape = strain.infection_escape;
+            if escape > 0.0 {
+                flows.flow(
+                    Some(layout.r_slot(0)),
+                    &[e0, reinf_cum],
+                    infections(layout.r_slot(0), 1.0 - escape),
+                );
+            }
+            // People ramping up to a dose are infected with the previous one
+            for dose in 0..doses {
+                let (exposed, ve_s) = match dose {
+                    0 => (e0, 0.0),
+                    _ => (ev(dose - 1, 0), efficacy[dose - 1].0),
+                };
+                flows.flow(
+                    Some(layout.sv_ramp_slot(dose)),
+                    &[exposed],
+                    infections(layout.sv_ramp_slot(dose), ve_s),
+                );
+                flows.flow(
+                    Some(layout.svr_ramp_slot(dose)),
+                    &[exposed, reinf_cum],
+                    infections(layout.svr_ramp_slot(dose), ve_s),
+                );
+            }
+            progress(flows, &e, latent_stages, latent_rate);
+            let e_last = e(latent_stages - 1);
+            let de_to_i = stage(e_last) * latent_rate;
+            let de_to_is = de_to_i.component_mul(fraction_symptomatic);
+            flows.flow_with_outcomes(
+                Some(e_last),
+                &counted(&[i(0), i_cum, y_cum]),
+                de_to_is.clone(),
+                &[(hospitalized, &p_hosp), (dead, &p_death)],
+            );
+            flows.flow_with_outcomes(
+                Some(e_last),
+                &counted(&[ia(0), i_cum]),
+                &de_to_i - &de_to_is,
+                &[(hospitalized, &p_hosp), (dead, &p_death)],
+            );
+            let infectious: [&dyn Fn(usize) -> usize; 2] = [&i, &ia];
+            for slot in infectious {
+                progress(flows, slot, infectious_stages, infectious_rate);
+                let last = slot(infectious_stages - 1);
+                flows.flow(
+                    Some(last),
+                    &[(slots.r)(layout, 0)],
+                    stage(last) * infectious_rate,
+                );
+            }
+
+            // Vaccinated
+            for (dose, &(ve_s, _, ve_p)) in efficacy.iter().enumerate() {
+                let ev0 = ev(dose, 0);
+                flows.flow(
+                    Some(layout.sv_slot(dose)),
+                    &[ev0],
+                    infections(layout.sv_slot(dose), ve_s),
+                );
+                flows.flow(
+                    Some(layout.svr_slot(dose)),
+                    &[ev0, reinf_cum],
+                    infections(layout.svr_slot(dose), ve_s),
+                );
+                if escape > 0.0 {
+                    flows.flow(
+                        Some(layout.rv_slot(dose)),
+                        &[ev0, reinf_cum],
+                        infections(layout.rv_slot(dose), 1.0 - escape * (1.0 - ve_s)),
+                    );
+                }
+                progress(flows, &|k| ev(dose, k), latent_stages, latent_rate);
+                let ev_last = ev(dose, latent_stages - 1);
+                let iv_cum = layout.iv_cum_slot(dose);
+                let dev_to_iv = stage(ev_last) * latent_rate;
+                let dev_to_ivs = dev_to_iv.component_mul(fraction_symptomatic) * (1.0 - ve_p);
+                let p_hosp = &p_hosp * (1.0 - ve_p);
+                let p_death = &p_death * (1.0 - ve_p);
+                flows.flow_with_outcomes(
+                    Some(ev_last),
+                    &counted(&[iva(dose, 0), iv_cum]),
+                    &dev_to_iv - &dev_to_ivs,
+                    &[(hospitalized, &p_hosp), (dead, &p_death)],
+                );
+                flows.flow_with_outcomes(
+                    Some(ev_last),
+                    &counted(&[iv(dose, 0), iv_cum, y_cum]),
+                    dev_to_ivs,
+                    &[(hospitalized, &p_hosp), (dead, &p_death)],
+                );
+                let infectious: [&dyn Fn(usize) -> usize; 2] =
+                    [&|k| iv(dose, k), &|k| iva(dose, k)];
+                for slot in infectious {
+                    progress(flows, slot, infectious_stages, infectious_rate);
+                    let last = slot(infectious_stages - 1);
+                    flows.flow(
+                        Some(last),
+                        &[(slots.rv)(layout, dose)],
+                        stage(last) * infectious_rate,
+                    );
+                }
+            }
+
+            // Waning immunity, back into the susceptible compartments for
+            // people who were previously infected so that reinfections can be
+            // counted
+            let r = (slots.r)(layout, 0);
+            flows.flow(
+                Some(r),
+                &[layout.sr_slot(0)],
+                stage(r) * params.recovered_waning_rate,
+            );
+            for dose in 0..doses {
+                let rv = (slots.rv)(layout, dose);
+                flows.flow(
+                    Some(rv),
+                    &[layout.svr_slot(dose)],
+                    stage(rv) * params.recovered_vaccinated_waning_rate,
+                );
+            }
+        }
+        for dose in 0..doses {
+            flows.flow(
+                Some(layout.sv_slot(dose)),
+                &[layout.s_slot(0)],
+                &sv[dose] * params.vaccine_waning_rate,
+            );
+            flows.flow(
+                Some(layout.svr_slot(dose)),
+                &[layout.sr_slot(0)],
+                &svr[dose] * params.vaccine_waning_rate,
+            );
+        }
+
+        // Vaccination. Only susceptible people change compartments, and
+        // doses to people in other disease states are only counted.
+        let ramp_up_period = vaccine_params.ramp_up_period;
+        for (dose, (per_capita, recipients)) in per_capita.iter().zip(&recipients).enumerate() {
+            let v_cum = layout.v_cum_slot(dose);
+            // Protection starts after the ramp-up period, or straight away without one
+            let (to_sv, to_svr) = if ramp_up_period > 0.0 {
+                (layout.sv_ramp_slot(dose), layout.svr_ramp_slot(dose))
+            } else {
+                (layout.sv_slot(dose), layout.svr_slot(dose))
+            };
+            let dosed = if dose == 0 {
+                vec![(layout.s_slot(0), to_sv), (layout.sr_slot(0), to_svr)]
+            } else {
+                vec![
+                    (layout.sv_slot(dose - 1), to_sv),
+                    (layout.sv_ramp_slot(dose - 1), to_sv),
+                    (layout.svr_slot(dose - 1), to_svr),
+                    (layout.svr_ramp_slot(dose - 1), to_svr),
+                ]
+            };
+            for (from, to) in dosed {
+                flows.flow(
+                    Some(from),
+                    &[to, v_cum, layout.v_state_cum_slot(0)],
+                    stage(from).component_mul(per_capita),
+                );
+            }
+            for (state, recipients) in recipients.iter().enumerate().skip(1) {
+                flows.flow(
+                    None,
+                    &[v_cum, layout.v_state_cum_slot(state)],
+                    per_capita.component_mul(recipients),
+                );
+            }
+            if ramp_up_period > 0.0 {
+                let (sv_ramp, svr_ramp) = &ramp[dose];
+                flows.flow(
+                    Some(layout.sv_ramp_slot(dose)),
+                    &[layout.sv_slot(dose)],
+                    sv_ramp / ramp_up_period,
+                );
+                flows.flow(
+                    Some(layout.svr_ramp_slot(dose)),
+                    &[layout.svr_slot(dose)],
+                    svr_ramp / ramp_up_period,
+                );
+            }
+        }
+
+        // Hospitalizations
+        let dpre_h_to_h_cum = pre_h / self.model.parameters.hospitalization_delay;
+        // Hospital admissions occupy an ICU or ward bed until discharge
+        let dneed_icu = dpre_h_to_h_cum.component_mul(&self.model.parameters.fraction_icu);
+        let dicu_out = icu.component_div(&self.model.parameters.icu_length_of_stay);
+        // Once all ICU beds are occupied, they are only given to new admissions
+        // as they are freed, and the others are cared for in a ward bed
+        let icu_accepted = match self.model.parameters.icu_capacity {
+            Some(capacity) if icu.sum() >= capacity && dneed_icu.sum() > 0.0 => {
+                (dicu_out.sum() / dneed_icu.sum()).min(1.0)
+            }
+            _ => 1.0,
+        };
+        let dto_icu = &dneed_icu * icu_accepted;
+        let dicu_overflow = &dneed_icu - &dto_icu;
+        // Excess deaths among those turned away from intensive care, who die
+        // on admission and so never occupy a ward bed
+        let dicu_overflow_to_d_cum =
+            dicu_overflow.component_mul(&self.model.parameters.fraction_dead_icu_overflow);
+        let dto_hosp = &dpre_h_to_h_cum - &dto_icu - &dicu_overflow_to_d_cum;
+        let dhosp_out = hosp.component_div(&self.model.parameters.hospital_length_of_stay);
+
+        // Deaths
+        let dpre_d_to_d_cum = pre_d / self.model.parameters.death_delay;
+
+        // Outcomes
+        let pre_h = layout.pre_h_slot(0);
+        let h_cum = layout.h_cum_slot(0);
+        let pre_d = layout.pre_d_slot(0);
+        let d_cum = layout.d_cum_slot(0);
+        flows.flow(Some(pre_h), &[h_cum, layout.icu_slot(0)], dto_icu);
+        flows.flow(Some(pre_h), &[h_cum, layout.hosp_slot(0)], dto_hosp);
+        flows.flow(Some(pre_h), &[h_cum, d_cum], dicu_overflow_to_d_cum);
+        flows.flow(Some(layout.icu_slot(0)), &[], dicu_out);
+        flows.flow(Some(layout.hosp_slot(0)), &[], dhosp_out);
+        flows.flow(Some(pre_d), &[d_cum], dpre_d_to_d_cum);
+    }
+}
+
+impl<D: Dim> System<f64, State> for SEIRSystem<'_, D>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    fn system(&self, x: f64, y: &State, dy: &mut State) {
+        dy.fill(0.0);
+        self.transitions(x, y, &mut Derivative(dy));
+    }
+}
+
+// Compute dominant eigenvalue and eigenvector of a non-negative matrix using
+// power algorithm. The matrix is shifted by a multiple of the identity, which
+// leaves the dominant eigenvector unchanged, so that the iteration does not
+// cycle when other eigenvalues have the same modulus (e.g., when people only
+// have contacts outside of their group).
+fn get_dominant_eigendata<D: Dim, S: Storage<f64, D, D>>(
+    matrix: &Matrix<f64, D, D, S>,
+) -> (f64, OVector<f64, D>)
+where
+    DefaultAllocator: Allocator<D>,
+{
+    let (d, _) = matrix.shape_generic();
+    let shift = 0.5 * matrix.column_sum().max();
+    let mut x = OVector::<f64, D>::from_element_generic(d, U1, 1.0 / d.value() as f64);
+    let mut norm = 0.0_f64;
+    for _ in 0..MAX_EIGEN_ITERATIONS {
+        x = matrix * &x + &x * shift;
+        let new_norm = x.lp_norm(1);
+        if new_norm == 0.0 {
+            break;
+        }
+        x /= new_norm;
+        let converged = (new_norm - norm).abs() <= EIGEN_TOLERANCE * new_norm;
+        norm = new_norm;
+        if converged {
+            break;
+        }
+    }
+    ((norm - shift).max(0.0), x)
+}
+
+#[cfg(test)]
+mod test {
+    use float_eq::assert_float_eq;
+    use nalgebra::{DVector, Matrix1, Matrix2, Vector1, Vector2, matrix, vector};
+
+    use strum::IntoEnumIterator;
+
+    use super::{ATOL, RTOL, SEIRModel, SEIRModelDyn};
+    use crate::{
+        AdministrationRateChange, AntiviralsParams, CommunityMitigationPeriod, ContactSetting,
+        DParameters, DynodeModel, ImportationPeriod, MitigationParams, MitigationRelease,
+        MitigationTrigger, ModelOutput, OutputType, Parameters, ParametersExport, RMultiplierPoint,
+        SettingContacts, SettingEffectiveness, VaccineAllocation, VaccineDelivery, VaccineParams,
+        VaccineTargeting, VariantParams,
+        model::{StateView, VaccineSchedule, get_dominant_eigendata},
+    };
+
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    struct TestResults<const N: usize> {
+        pub attack_rate: f64,
         pub symptomatic_rate: f64,
         pub hospitalization_rate: f64,
         pub death_rate: f64,
     }
 
-    impl<const N: usize> TestResults<N> {
-        pub fn new(params: &Parameters<N>, output: &ModelOutput) -> Self {
-            let total_incidence: f64 = output
-                .get_output(&OutputType::InfectionIncidence)
-                .iter()
-                .map(|x| x.grouped_values.iter().sum::<f64>())
-                .sum();
+    impl<const N: usize> TestResults<N> {
+        pub fn new(params: &Parameters<N>, output: &ModelOutput) -> Self {
+            let total_incidence: f64 = output
+                .get_output(&OutputType::InfectionIncidence)
+                .iter()
+                .map(|x| x.grouped_values.iter().sum::<f64>())
+                .sum();
+
+            let symptomatic_incidence: f64 = output
+                .get_output(&OutputType::SymptomaticIncidence)
+                .iter()
+                .map(|x| x.grouped_values.iter().sum::<f64>())
+                .sum();
+
+            let hospitalization_incidence: f64 = output
+                .get_output(&OutputType::HospitalIncidence)
+                .iter()
+                .map(|x| x.grouped_values.iter().sum::<f64>())
+                .sum();
+
+            let death_incidence: f64 = output
+                .get_output(&OutputType::DeathIncidence)
+                .iter()
+                .map(|x| x.grouped_values.iter().sum::<f64>())
+                .sum();
+
+            TestResults {
+                attack_rate: total_incidence / params.population,
+                symptomatic_rate: symptomatic_incidence / params.population,
+                hospitalization_rate: hospitalization_incidence / params.population,
+                death_rate: death_incidence / params.population,
+            }
+        }
+    }
+
+    /// A single group matching the reference SEIRTVModel scenarios below
+    fn single_group_params() -> Parameters<1> {
+        Parameters {
+            population: 330_000_000.0,
+            population_fractions: Vector1::new(1.0),
+            population_fraction_labels: Vector1::new("All".to_string()),
+            contact_matrix: Matrix1::new(1.0),
+            setting_contacts: Vec::new(),
+            relative_susceptibility: Vector1::new(1.0),
+            relative_infectiousness: Vector1::new(1.0),
+            initial_infections: 1000.0,
+            initial_infection_shares: None,
+            initial_immunity: Vector1::new(0.0),
+            initial_vaccinated: Vector1::new(0.0),
+            importations: Vec::new(),
+            r0: 2.0,
+            seasonal_amplitude: 0.0,
+            seasonal_peak_day: 0.0,
+            r_multiplier: Vec::new(),
+            latent_period: 1.0,
+            latent_stages: 1,
+            infectious_period: 3.0,
+            infectious_stages: 1,
+            recovered_waning_rate: 0.0,
+            recovered_vaccinated_waning_rate: 0.0,
+            vaccine_waning_rate: 0.0,
+            mitigations: MitigationParams::default(),
+            fraction_symptomatic: Vector1::new(0.5),
+            asymptomatic_relative_infectiousness: 1.0,
+            fraction_hospitalized: Vector1::new(0.0),
+            hospitalization_delay: 1.0,
+            hospital_length_of_stay: Vector1::new(5.0),
+            fraction_icu: Vector1::new(0.0),
+            icu_length_of_stay: Vector1::new(10.0),
+            icu_capacity: None,
+            fraction_dead_icu_overflow: Vector1::new(0.0),
+            fraction_dead: Vector1::new(0.0),
+            death_delay: 1.0,
+            p_test_sympto: 0.0,
+            test_sensitivity: 0.90,
+            p_test_forward: 0.90,
+            variant: None,
+            stochastic: None,
+        }
+    }
+
+    // population <- 3.3e8
+    // SEIRTVModel(
+    //     simulationLength = 300,
+    //     population = population,
+    //     R0 = 2.0,
+    //     latentPeriod = 1.0,
+    //     infectiousPeriod = 3.0,
+    //     seedInfections = 1000.0 / population,
+    //     tolerance = 1e-6,
+    // )
+    #[test]
+    fn test_seir_unmitigated() {
+        let model = SEIRModel::new(single_group_params());
+        let results = TestResults::new(&model.parameters, &model.integrate(300));
+        assert_float_eq!(results.attack_rate, 0.796814, abs <= 1e-5);
+    }
+
+    // population <- 3.3e8
+    // SEIRTVModel(
+    //     simulationLength = 300,
+    //     population = population,
+    //     R0 = 2.0,
+    //     latentPeriod = 1.0,
+    //     infectiousPeriod = 3.0,
+    //     seedInfections = 1000.0 / population,
+    //     tolerance = 1e-8,
+    //     VEs = 0.5,
+    //     VEi = 0.5,
+    //     VEp = 0.5,
+    //     vaccineAdministrationRatePerDay = 1e6,
+    //     vaccineAvailabilityByDay = c(2e7),
+    // )
+    #[test]
+    fn test_seir_vaccine() {
+        let vaccine_params = VaccineParams {
+            enabled: true,
+            editable: true,
+            doses: 1,
+            dose_interval: 28.0,
+            start: 0.0,
+            administration_rate: 1_000_000.0,
+            doses_available: 20_000_000.0,
+            administration_rate_changes: vec![],
+            deliveries: vec![],
+            ve_s: 0.5,
+            ve_i: 0.5,
+            ve_p: 0.5,
+            partial_ve_s: vec![],
+            partial_ve_i: vec![],
+            partial_ve_p: vec![],
+            ramp_up_period: 0.0,
+            targeting: VaccineTargeting::All,
+            allocation: VaccineAllocation::ProRata,
+            coverage_caps: vec![],
+        };
+
+        let mut params = single_group_params();
+        params.mitigations.vaccine = vaccine_params;
+        let model = SEIRModel::new(params);
+        let results = TestResults::new(&model.parameters, &model.integrate(300));
+        let expected = 0.7583813;
+        assert_float_eq!(results.attack_rate, expected, abs <= 1e-5);
+    }
+
+    fn peak_day(output: &ModelOutput) -> f64 {
+        output
+            .get_output(&OutputType::InfectionIncidence)
+            .iter()
+            .map(|x| (x.time, x.grouped_values.iter().sum::<f64>()))
+            .fold((0.0, 0.0), |peak, x| if x.1 > peak.1 { x } else { peak })
+            .0
+    }
+
+    #[test]
+    fn test_erlang_stages() {
+        let exponential = single_group_params();
+        let erlang = Parameters {
+            latent_stages: 2,
+            infectious_stages: 3,
+            ..exponential.clone()
+        };
+
+        let exponential_output = SEIRModel::new(exponential.clone()).integrate(300);
+        let erlang_output = SEIRModel::new(erlang.clone()).integrate(300);
+
+        // The final size only depends on R0, not the shape of the periods
+        let results = TestResults::new(&erlang, &erlang_output);
+        assert_float_eq!(results.attack_rate, 0.796814, abs <= 1e-4);
+
+        // Less dispersed generation intervals make for faster growth
+        assert!(peak_day(&erlang_output) < peak_day(&exponential_output));
+    }
+
+    fn total_by_day(output: &ModelOutput, output_type: &OutputType) -> Vec<f64> {
+        output
+            .get_output(output_type)
+            .iter()
+            .map(|x| x.grouped_values.iter().sum::<f64>())
+            .collect()
+    }
+
+    #[test]
+    fn test_r_multiplier() {
+        let params = Parameters {
+            r0: 2.0,
+            ..Default::default()
+        };
+        let halved = Parameters {
+            r_multiplier: vec![RMultiplierPoint {
+                day: 0.0,
+                multiplier: 0.5,
+            }],
+            ..params.clone()
+        };
+        let lower_r0 = Parameters { r0: 1.0, ..params };
+
+        // A constant multiplier is the same as changing R0
+        let halved = total_by_day(
+            &SEIRModel::new(halved).integrate(300),
+            &OutputType::InfectionIncidence,
+        );
+        let lower_r0 = total_by_day(
+            &SEIRModel::new(lower_r0).integrate(300),
+            &OutputType::InfectionIncidence,
+        );
+        for (a, b) in halved.iter().zip(lower_r0.iter()) {
+            assert_float_eq!(
+                a,
+                b,
+                abs <= 1e-3 * lower_r0.iter().fold(0.0, |m, &x| f64::max(m, x))
+            );
+        }
+    }
+
+    #[test]
+    fn test_initial_conditions() {
+        let params = Parameters {
+            initial_infection_shares: Some(vector![0.0, 1.0]),
+            initial_immunity: vector![0.0, 0.4],
+            initial_vaccinated: vector![0.2, 0.1],
+            ..Default::default()
+        };
+        let model = SEIRModel::new(params.clone());
+        let state = model.initial_state();
+        let initial = StateView::new(&state, &model.layout, model.d);
+        let children = 0.25 * params.population;
+        let adults = 0.75 * params.population;
+        assert_eq!(initial.get_i() + initial.get_ia(), vector![0.0, 1_000.0]);
+        assert_eq!(initial.get_r(), vector![0.0, 0.4 * adults]);
+        assert_eq!(initial.get_sv(), vector![0.2 * children, 0.1 * adults]);
+        assert_eq!(
+            initial.get_s(),
+            vector![0.8 * children, 0.5 * adults - 1_000.0]
+        );
+        assert_eq!(state.sum(), params.population);
+
+        // Prior immunity protects from infection
+        let baseline = TestResults::new(
+            &params,
+            &SEIRModel::new(Parameters::default()).integrate(300),
+        );
+        let results = TestResults::new(&params, &model.integrate(300));
+        assert!(results.attack_rate < baseline.attack_rate);
+
+        // People initially vaccinated count towards the coverage cap
+        let mut params = params;
+        params.mitigations.vaccine.coverage_caps = vec![1.0, 0.1];
+        let model = SEIRModel::new(params);
+        let everyone = model.parameters.population_fractions * model.parameters.population;
+        assert_eq!(
+            model.first_dose_shares(&Vector2::zeros(), &everyone),
+            Vector2::new(1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_variant() {
+        let variant = VariantParams {
+            relative_transmissibility: 1.0,
+            introduction_day: 0,
+            introduced_infections: 500.0,
+            vaccine_escape: 0.0,
+            infection_escape: 0.0,
+        };
+        let by_strain = |output: &ModelOutput| {
+            (
+                total_by_day(output, &OutputType::FirstStrainInfectionIncidence),
+                total_by_day(output, &OutputType::VariantInfectionIncidence),
+            )
+        };
+        // A variant like the first strain, introduced alongside it, infects
+        // about as many people, and both together as many as the first
+        // strain alone
+        let baseline = SEIRModel::new(Parameters::default()).integrate(300);
+        let output = SEIRModel::new(Parameters {
+            initial_infections: 500.0,
+            variant: Some(variant),
+            ..Default::default()
+        })
+        .integrate(300);
+        let (first, second) = by_strain(&output);
+        let (first, second) = (first.iter().sum::<f64>(), second.iter().sum::<f64>());
+        // The introduced infections are taken from the susceptible people,
+        // in proportion to the group sizes
+        let model = SEIRModel::new(Parameters {
+            initial_infections: 500.0,
+            variant: Some(variant),
+            ..Default::default()
+        });
+        let state = model.initial_state();
+        let initial = StateView::new(&state, &model.layout, model.d);
+        let without_variant = SEIRModel::new(Parameters {
+            initial_infections: 500.0,
+            ..Default::default()
+        });
+        let baseline_state = without_variant.initial_state();
+        let without = StateView::new(&baseline_state, &without_variant.layout, without_variant.d);
+        let fractions = &model.parameters.population_fractions;
+        assert_eq!(initial.get_s(), without.get_s() - fractions * 500.0);
+        assert_eq!(state.sum(), model.parameters.population);
+        let total = total_by_day(&baseline, &OutputType::InfectionIncidence);
+        assert_float_eq!(first + second, total.iter().sum::<f64>(), rmax <= 0.01);
+        assert_float_eq!(first, second, rmax <= 0.2);
+
+        // A more transmissible variant takes over after its introduction
+        let params = Parameters {
+            variant: Some(VariantParams {
+                relative_transmissibility: 1.5,
+                introduction_day: 30,
+                introduced_infections: 10.0,
+                ..variant
+            }),
+            ..Default::default()
+        };
+        let output = SEIRModel::new(params.clone()).integrate(300);
+        let (first, second) = by_strain(&output);
+        assert!(second[..30].iter().all(|&x| x == 0.0));
+        assert!(second.iter().sum::<f64>() > first.iter().sum::<f64>());
+        for ((first, second), total) in first
+            .iter()
+            .zip(&second)
+            .zip(&total_by_day(&output, &OutputType::InfectionIncidence))
+        {
+            assert_float_eq!(first + second, *total, abs <= 1e-6);
+        }
+
+        // After the first wave, only a variant that escapes immunity from
+        // infection spreads, as reinfections
+        let after_first_wave = |infection_escape| {
+            let output = SEIRModel::new(Parameters {
+                variant: Some(VariantParams {
+                    relative_transmissibility: 1.5,
+                    introduction_day: 250,
+                    introduced_infections: 1_000.0,
+                    infection_escape,
+                    ..variant
+                }),
+                ..Default::default()
+            })
+            .integrate(500);
+            let reinfections = total_by_day(&output, &OutputType::ReinfectionIncidence);
+            let (_, second) = by_strain(&output);
+            // Rt on the day of the introduction, counting the variant
+            let rt = output.get_rt()[250].value;
+            (
+                second.iter().sum::<f64>(),
+                reinfections.iter().sum::<f64>(),
+                rt,
+            )
+        };
+        let (escaping, reinfections, escaping_rt) = after_first_wave(0.8);
+        let (immune, no_reinfections, immune_rt) = after_first_wave(0.0);
+        assert!(escaping > 10.0 * immune);
+        assert!(escaping_rt > immune_rt + 0.5);
+        assert!(reinfections > 0.0);
+        assert_eq!(no_reinfections, 0.0);
+
+        // Escaping vaccines makes the variant spread more among the vaccinated
+        let vaccinated = |vaccine_escape| {
+            let mut params = Parameters {
+                initial_vaccinated: vector![0.5, 0.5],
+                variant: Some(VariantParams {
+                    vaccine_escape,
+                    ..variant
+                }),
+                ..Default::default()
+            };
+            params.mitigations.vaccine.ve_s = 0.8;
+            let (_, second) = by_strain(&SEIRModel::new(params).integrate(300));
+            second.iter().sum::<f64>()
+        };
+        assert!(vaccinated(0.5) > vaccinated(0.0));
+    }
+
+    #[test]
+    fn test_importations() {
+        let period = |start, rate, group_shares| ImportationPeriod {
+            start,
+            rate,
+            group_shares,
+        };
+        // Without transmission, imported infections only become infectious
+        let params = Parameters {
+            r0: 0.0,
+            initial_infections: 0.0,
+            importations: vec![period(10.0, 10.0, Some(vec![1.0, 0.0]))],
+            ..Default::default()
+        };
+        let output = SEIRModel::new(params).integrate(100);
+        let incidence = output.get_output(&OutputType::InfectionIncidence);
+        assert!(incidence[..10].iter().all(|x| x.grouped_values[0] < 1e-6));
+        assert!(incidence.iter().all(|x| x.grouped_values[1] == 0.0));
+        // All 900 imported infections are counted, less those still in their
+        // latent period at the end
+        let total = incidence.iter().map(|x| x.grouped_values[0]).sum::<f64>();
+        assert_float_eq!(total, 900.0 - 10.0, abs <= 1.0);
+
+        // Reducing importations tenfold delays the epidemic by about
+        // ln(10) / r, or 18 days
+        let params = Parameters {
+            initial_infections: 0.0,
+            importations: vec![period(0.0, 10.0, None)],
+            ..Default::default()
+        };
+        let reduced = Parameters {
+            importations: vec![period(0.0, 1.0, None)],
+            ..params.clone()
+        };
+        let peak = peak_day(&SEIRModel::new(params).integrate(300));
+        let reduced_peak = peak_day(&SEIRModel::new(reduced).integrate(300));
+        assert_float_eq!(reduced_peak - peak, 18.0, abs <= 3.0);
+
+        // Imported infections come from the population, and so eventually run
+        // out however many are imported
+        let params = Parameters {
+            r0: 0.0,
+            initial_infections: 0.0,
+            ..Default::default()
+        };
+        let params = Parameters {
+            importations: vec![period(0.0, 0.1 * params.population, None)],
+            ..params
+        };
+        let output = SEIRModel::new(params.clone()).integrate(300);
+        let total = total_by_day(&output, &OutputType::InfectionIncidence)
+            .iter()
+            .sum::<f64>();
+        assert!(total <= params.population * (1.0 + 1e-6));
+        assert!(total > 0.99 * params.population);
+    }
+
+    #[test]
+    fn test_seasonal_forcing() {
+        let params = Parameters {
+            r0: 1.3,
+            seasonal_amplitude: 0.2,
+            ..Default::default()
+        };
+        // Transmission peaks on the first day ("winter") or half a year later
+        // ("summer")
+        let winter = SEIRModel::new(params.clone()).integrate(365);
+        let summer = SEIRModel::new(Parameters {
+            seasonal_peak_day: 182.5,
+            ..params
+        })
+        .integrate(365);
+        let early_infections = |output: &ModelOutput| -> f64 {
+            total_by_day(output, &OutputType::InfectionIncidence)[..60]
+                .iter()
+                .sum()
+        };
+        assert!(early_infections(&winter) > early_infections(&summer));
+        assert!(peak_day(&winter) < peak_day(&summer));
+    }
+
+    #[test]
+    fn test_overlapping_community_mitigations() {
+        let period = |start, duration, effectiveness| CommunityMitigationPeriod {
+            start,
+            duration,
+            effectiveness: Matrix2::from_element(effectiveness),
+            setting_effectiveness: vec![],
+            trigger: MitigationTrigger::Day,
+            release: MitigationRelease::Duration,
+        };
+        let with_periods = |periods| {
+            let mut params = Parameters {
+                r0: 2.0,
+                ..Default::default()
+            };
+            params.mitigations.community.enabled = true;
+            params.mitigations.community.periods = periods;
+            let output = SEIRModel::new(params).integrate(200);
+            total_by_day(&output, &OutputType::InfectionIncidence)
+        };
+
+        // Two overlapping periods are equivalent to three consecutive ones,
+        // with the combined effectiveness while they overlap
+        let overlapping = with_periods(vec![period(30.0, 30.0, 0.4), period(45.0, 30.0, 0.5)]);
+        let consecutive = with_periods(vec![
+            period(30.0, 15.0, 0.4),
+            period(45.0, 15.0, 1.0 - 0.6 * 0.5),
+            period(60.0, 15.0, 0.5),
+        ]);
+        let single = with_periods(vec![period(30.0, 30.0, 0.4)]);
+        let peak = overlapping.iter().fold(0.0, |m: f64, &x| m.max(x));
+        for (a, b) in overlapping.iter().zip(consecutive.iter()) {
+            assert_float_eq!(a, b, abs <= 1e-4 * peak);
+        }
+        assert!(overlapping[..75].iter().sum::<f64>() < single[..75].iter().sum::<f64>());
+    }
+
+    #[test]
+    fn test_setting_contacts() {
+        let home = matrix![6.0, 2.0; 6.0, 6.0];
+        let school = matrix![12.0, 1.0; 3.0, 6.0];
+        let params = Parameters {
+            r0: 2.0,
+            ..Default::default()
+        };
+        let by_setting = Parameters {
+            contact_matrix: Matrix2::zeros(),
+            setting_contacts: vec![
+                SettingContacts {
+                    setting: ContactSetting::Home,
+                    contact_matrix: home,
+                },
+                SettingContacts {
+                    setting: ContactSetting::School,
+                    contact_matrix: school,
+                },
+            ],
+            ..params.clone()
+        };
+        assert_eq!(by_setting.total_contact_matrix(), params.contact_matrix);
+
+        let with_period = |mut params: Parameters<2>, effectiveness, setting_effectiveness| {
+            params.mitigations.community.enabled = true;
+            params.mitigations.community.periods = vec![CommunityMitigationPeriod {
+                start: 30.0,
+                duration: 30.0,
+                effectiveness,
+                setting_effectiveness,
+                trigger: MitigationTrigger::Day,
+                release: MitigationRelease::Duration,
+            }];
+            let output = SEIRModel::new(params).integrate(200);
+            total_by_day(&output, &OutputType::InfectionIncidence)
+        };
+
+        // Closing schools is the same as reducing contacts between each pair
+        // of groups by the share of their contacts made at school
+        let closed = with_period(
+            by_setting,
+            Matrix2::zeros(),
+            vec![SettingEffectiveness {
+                setting: ContactSetting::School,
+                effectiveness: 0.9,
+            }],
+        );
+        let effectiveness = (school * 0.9).component_div(&params.contact_matrix);
+        let equivalent = with_period(params, effectiveness, vec![]);
+        let peak = equivalent.iter().fold(0.0, |m: f64, &x| m.max(x));
+        for (a, b) in closed.iter().zip(equivalent.iter()) {
+            assert_float_eq!(a, b, abs <= 1e-6 * peak);
+        }
+    }
+
+    #[test]
+    fn test_triggered_community_mitigation() {
+        let with_period = |trigger, release| {
+            let mut params = Parameters {
+                r0: 2.0,
+                p_test_sympto: 1e-5,
+                ..Default::default()
+            };
+            params.mitigations.community.enabled = true;
+            params.mitigations.community.periods = vec![CommunityMitigationPeriod {
+                start: 10.0,
+                duration: 30.0,
+                effectiveness: Matrix2::from_element(0.5),
+                setting_effectiveness: vec![],
+                trigger,
+                release,
+            }];
+            SEIRModel::new(params).integrate(200)
+        };
+        let admissions_per_100k = |output: &ModelOutput| {
+            total_by_day(output, &OutputType::HospitalIncidence)
+                .iter()
+                .map(|x| x / 330_000_000.0 * 100_000.0)
+                .collect::<Vec<_>>()
+        };
+
+        // Put in effect on the first day admissions reach the threshold, and
+        // lifted after the given duration
+        let output = with_period(
+            MitigationTrigger::HospitalAdmissions(0.5),
+            MitigationRelease::Duration,
+        );
+        let [window] = output.get_community_mitigations().as_slice() else {
+            panic!("expected a single community mitigation");
+        };
+        let admissions = admissions_per_100k(&output);
+        let start = window.start as usize;
+        assert!(window.start > 10.0);
+        assert!(admissions[start - 1] >= 0.5);
+        assert!(admissions[start - 2] < 0.5);
+        assert_eq!(window.end, Some(window.start + 30.0));
+
+        // Lifted once admissions fall below a lower threshold
+        let output = with_period(
+            MitigationTrigger::HospitalAdmissions(0.5),
+            MitigationRelease::HospitalAdmissions(0.45),
+        );
+        let [window] = output.get_community_mitigations().as_slice() else {
+            panic!("expected a single community mitigation");
+        };
+        let admissions = admissions_per_100k(&output);
+        let end = window.end.unwrap() as usize;
+        assert!(admissions[end - 1] < 0.45);
+        assert!(admissions[start..end - 1].iter().all(|&x| x >= 0.45));
+
+        // Put in effect once the epidemic is likely to have been detected
+        let output = with_period(
+            MitigationTrigger::Detection(0.9),
+            MitigationRelease::Duration,
+        );
+        let [window] = output.get_community_mitigations().as_slice() else {
+            panic!("expected a single community mitigation");
+        };
+        let p_detect = output.get_p_detect();
+        let start = window.start as usize;
+        assert!(p_detect[start - 1].value >= 0.9);
+        assert!(p_detect[start - 2].value < 0.9);
+
+        // Never triggered
+        let output = with_period(
+            MitigationTrigger::HospitalAdmissions(1e6),
+            MitigationRelease::Duration,
+        );
+        assert!(output.get_community_mitigations().is_empty());
+    }
+
+    #[test]
+    fn test_waning_immunity() {
+        let params = Parameters {
+            r0: 2.0,
+            ..Default::default()
+        };
+        let days = 5 * 365;
+
+        // Immunity is lifelong by default, so the epidemic burns out
+        let output = SEIRModel::new(params.clone()).integrate(days);
+        let reinfections = total_by_day(&output, &OutputType::ReinfectionIncidence);
+        assert_eq!(reinfections.len(), days);
+        assert!(reinfections.iter().all(|x| *x == 0.0));
+
+        // With waning, transmission becomes endemic
+        let output = SEIRModel::new(Parameters {
+            recovered_waning_rate: 1.0 / 365.0,
+            ..params
+        })
+        .integrate(days);
+        let infections = total_by_day(&output, &OutputType::InfectionIncidence);
+        let reinfections = total_by_day(&output, &OutputType::ReinfectionIncidence);
+        assert_eq!(infections.len(), days);
+        assert!(infections[days - 365..].iter().all(|x| *x > 1_000.0));
+        assert!(reinfections[days - 365..].iter().sum::<f64>() > 0.0);
+        assert!(
+            reinfections
+                .iter()
+                .zip(infections.iter())
+                .all(|(reinfections, infections)| reinfections <= infections)
+        );
+    }
+
+    #[test]
+    fn test_vaccine_waning() {
+        let mut params = Parameters::default();
+        params.mitigations.vaccine.enabled = true;
+        let model = SEIRModel::new(params.clone());
+        let results = TestResults::new(&model.parameters, &model.integrate(300));
+
+        let waning_model = SEIRModel::new(Parameters {
+            vaccine_waning_rate: 1.0 / 30.0,
+            ..params
+        });
+        let waning_results =
+            TestResults::new(&waning_model.parameters, &waning_model.integrate(300));
+        assert!(waning_results.attack_rate > results.attack_rate);
+    }
+
+    #[test]
+    fn test_vaccine_ramp_up() {
+        let mut params = Parameters::default();
+        params.mitigations.vaccine.enabled = true;
+        let model = SEIRModel::new(params.clone());
+        let results = TestResults::new(&model.parameters, &model.integrate(300));
+
+        params.mitigations.vaccine.ramp_up_period = 14.0;
+        let ramp_up_model = SEIRModel::new(params);
+        let ramp_up_results =
+            TestResults::new(&ramp_up_model.parameters, &ramp_up_model.integrate(300));
+        assert!(ramp_up_results.attack_rate > results.attack_rate);
+    }
+
+    #[test]
+    fn test_first_dose_shares() {
+        let mut params = Parameters::default();
+        params.mitigations.vaccine.coverage_caps = vec![1.0, 0.5];
+        let unvaccinated = Vector2::zeros();
+        let everyone = params.population_fractions * params.population;
+        let adults_capped = Vector2::new(0.0, 0.5 * 0.75 * params.population);
+        let shares = |params: &Parameters<2>, vaccinated| {
+            SEIRModel::new(params.clone()).first_dose_shares(vaccinated, &everyone)
+        };
+
+        assert_eq!(shares(&params, &unvaccinated), Vector2::new(0.25, 0.75));
+        assert_eq!(shares(&params, &adults_capped), Vector2::new(1.0, 0.0));
+
+        params.mitigations.vaccine.allocation = VaccineAllocation::Priority(vec![1, 0]);
+        assert_eq!(shares(&params, &unvaccinated), Vector2::new(0.0, 1.0));
+        assert_eq!(shares(&params, &adults_capped), Vector2::new(1.0, 0.0));
+
+        params.mitigations.vaccine.allocation = VaccineAllocation::Shares(vec![1.0, 3.0]);
+        assert_eq!(shares(&params, &unvaccinated), Vector2::new(0.25, 0.75));
+        assert_eq!(shares(&params, &adults_capped), Vector2::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn test_vaccine_priority() {
+        let infections_by_group = |order: Vec<usize>| {
+            let mut params = Parameters::default();
+            params.mitigations.vaccine.enabled = true;
+            params.mitigations.vaccine.allocation = VaccineAllocation::Priority(order);
+            SEIRModel::new(params)
+                .integrate(300)
+                .get_output(&OutputType::InfectionIncidence)
+                .iter()
+                .fold(vec![0.0; 2], |acc, x| {
+                    acc.iter()
+                        .zip(&x.grouped_values)
+                        .map(|(a, b)| a + b)
+                        .collect()
+                })
+        };
+        let children_first = infections_by_group(vec![0, 1]);
+        let adults_first = infections_by_group(vec![1, 0]);
+        assert!(children_first[0] < adults_first[0]);
+        // Children drive transmission in the default contact matrix, so that
+        // vaccinating them first also protects adults
+        assert!(children_first[1] < adults_first[1]);
+    }
+
+    #[test]
+    fn test_vaccine_targeting() {
+        let run_with = |targeting, params: Parameters<2>| {
+            let mut params = params;
+            params.mitigations.vaccine.enabled = true;
+            params.mitigations.vaccine.start = 60.0;
+            params.mitigations.vaccine.targeting = targeting;
+            let model = SEIRModel::new(params);
+            let output = model.integrate(300);
+            let total = |output_type| total_by_day(&output, &output_type).iter().sum::<f64>();
+            let doses = total(OutputType::DosesAdministered);
+            let by_state = [
+                total(OutputType::DosesToSusceptible),
+                total(OutputType::DosesToExposed),
+                total(OutputType::DosesToInfectious),
+                total(OutputType::DosesToRecovered),
+            ];
+            assert_float_eq!(by_state.iter().sum::<f64>(), doses, rmax <= 1e-6);
+            (TestResults::new(&model.parameters, &output), by_state)
+        };
+        let run = |targeting| run_with(targeting, Parameters::default());
+
+        let (all, all_doses) = run(VaccineTargeting::All);
+        let (seronegative, seronegative_doses) = run(VaccineTargeting::Seronegative);
+        let (susceptible, susceptible_doses) = run(VaccineTargeting::Susceptible);
+        assert!(all_doses.iter().all(|doses| *doses > 0.0));
+        assert!(seronegative_doses[2] > 0.0);
+        assert_eq!(seronegative_doses[3], 0.0);
+        assert_eq!(susceptible_doses[1..], [0.0; 3]);
+        assert!(seronegative.attack_rate < all.attack_rate);
+        assert!(susceptible.attack_rate < seronegative.attack_rate);
+
+        // People recovered from asymptomatic infections are seropositive too
+        let asymptomatic = Parameters {
+            fraction_symptomatic: Vector2::zeros(),
+            ..Default::default()
+        };
+        let (_, all_doses) = run_with(VaccineTargeting::All, asymptomatic.clone());
+        let (_, seronegative_doses) = run_with(VaccineTargeting::Seronegative, asymptomatic);
+        assert!(all_doses[3] > 0.0);
+        assert!(seronegative_doses[1] > 0.0 && seronegative_doses[2] > 0.0);
+        assert_eq!(seronegative_doses[3], 0.0);
+    }
+
+    #[test]
+    fn test_vaccine_schedule() {
+        let mut params = MitigationParams::<1>::default().vaccine;
+        params.enabled = true;
+        params.doses = 2;
+        params.dose_interval = 10.0;
+        params.start = 5.0;
+        params.administration_rate = 1.0;
+        params.doses_available = 35.0;
+        let schedule = VaccineSchedule::new(&params);
+        let rates = |x| (schedule.rate(x, 0), schedule.rate(x, 1));
+        assert_eq!(rates(0.0), (0.0, 0.0));
+        assert_eq!(rates(10.0), (1.0, 0.0));
+        // Second doses are due one interval after the first ones
+        assert_eq!(rates(20.0), (0.0, 1.0));
+        assert_eq!(rates(30.0), (1.0, 0.0));
+        assert_eq!(rates(38.0), (0.0, 1.0));
+        // The available doses are used up
+        assert_eq!(rates(41.0), (0.0, 0.0));
+    }
 
-            let symptomatic_incidence: f64 = output
-                .get_output(&OutputType::SymptomaticIncidence)
-                .iter()
-                .map(|x| x.grouped_values.iter().sum::<f64>())
-                .sum();
+    #[test]
+    fn test_vaccine_supply() {
+        let mut params = MitigationParams::<1>::default().vaccine;
+        params.enabled = true;
+        params.start = 0.0;
+        params.administration_rate = 1.0;
+        params.doses_available = 5.0;
+        params.deliveries = vec![VaccineDelivery {
+            day: 10.0,
+            doses: 5.0,
+        }];
+        params.administration_rate_changes = vec![AdministrationRateChange {
+            day: 12.0,
+            rate: 2.0,
+        }];
+        let schedule = VaccineSchedule::new(&params);
+        assert_eq!(schedule.rate(2.0, 0), 1.0);
+        assert_eq!(schedule.rate(7.0, 0), 0.0);
+        assert_eq!(schedule.rate(11.0, 0), 1.0);
+        assert_eq!(schedule.rate(13.0, 0), 2.0);
+        assert_eq!(schedule.rate(14.0, 0), 0.0);
+        assert_eq!(schedule.delivered(9.0), 5.0);
+        assert_eq!(schedule.delivered(10.0), 10.0);
 
-            let hospitalization_incidence: f64 = output
-                .get_output(&OutputType::HospitalIncidence)
-                .iter()
-                .map(|x| x.grouped_values.iter().sum::<f64>())
-                .sum();
+        // Second doses that are due while the stockpile is empty are given
+        // once more doses are delivered
+        params.doses = 2;
+        params.dose_interval = 10.0;
+        params.partial_ve_s = vec![0.0];
+        params.partial_ve_i = vec![0.0];
+        params.partial_ve_p = vec![0.0];
+        params.doses_available = 10.0;
+        params.deliveries = vec![VaccineDelivery {
+            day: 25.0,
+            doses: 10.0,
+        }];
+        params.administration_rate_changes = vec![];
+        let schedule = VaccineSchedule::new(&params);
+        let rates = |x| (schedule.rate(x, 0), schedule.rate(x, 1));
+        assert_eq!(rates(5.0), (1.0, 0.0));
+        assert_eq!(rates(15.0), (0.0, 0.0));
+        assert_eq!(rates(30.0), (0.0, 1.0));
+        assert_eq!(rates(36.0), (0.0, 0.0));
+    }
 
-            let death_incidence: f64 = output
-                .get_output(&OutputType::DeathIncidence)
+    #[test]
+    fn test_vaccine_stockpile() {
+        let mut params = Parameters::default();
+        params.mitigations.vaccine.enabled = true;
+        params.mitigations.vaccine.deliveries = vec![VaccineDelivery {
+            day: 60.0,
+            doses: 10_000_000.0,
+        }];
+        let output = SEIRModel::new(params).integrate(300);
+        let doses = total_by_day(&output, &OutputType::DosesAdministered);
+        assert_float_eq!(doses.iter().sum::<f64>(), 50_000_000.0, rmax <= 1e-4);
+        let stockpile = |day| {
+            output
+                .get_vaccine_stockpile()
                 .iter()
-                .map(|x| x.grouped_values.iter().sum::<f64>())
-                .sum();
-
-            TestResults {
-                attack_rate: total_incidence / params.population,
-                symptomatic_rate: symptomatic_incidence / params.population,
-                hospitalization_rate: hospitalization_incidence / params.population,
-                death_rate: death_incidence / params.population,
-            }
-        }
+                .find(|item| item.time == day)
+                .unwrap()
+                .value
+        };
+        // Up to the solver's error around the sudden stock-outs
+        assert_float_eq!(stockpile(40.0), 0.0, abs <= 1e4);
+        assert_float_eq!(stockpile(61.0), 8_500_000.0, abs <= 1e4);
+        assert_float_eq!(stockpile(300.0), 0.0, abs <= 1e4);
     }
 
-    // population <- 3.3e8
-    // SEIRTVModel(
-    //     simulationLength = 300,
-    //     population = population,
-    //     R0 = 2.0,
-    //     latentPeriod = 1.0,
-    //     infectiousPeriod = 3.0,
-    //     seedInfections = 1000.0 / population,
-    //     tolerance = 1e-6,
-    // )
     #[test]
-    fn test_seir_unmitigated() {
-        let model = SEIRModel::new(Parameters {
-            population: 330_000_000.0,
-            population_fractions: Vector1::new(1.0),
-            population_fraction_labels: Vector1::new("All".to_string()),
-            contact_matrix: Matrix1::new(1.0),
-            initial_infections: 1000.0,
-            r0: 2.0,
-            latent_period: 1.0,
-            infectious_period: 3.0,
-            mitigations: MitigationParams::default(),
-            fraction_symptomatic: Vector1::new(0.5),
-            fraction_hospitalized: Vector1::new(0.0),
-            hospitalization_delay: 1.0,
-            fraction_dead: Vector1::new(0.0),
-            death_delay: 1.0,
-            p_test_sympto: 0.0,
-            test_sensitivity: 0.90,
-            p_test_forward: 0.90,
-        });
-        let results = TestResults::new(&model.parameters, &model.integrate(300));
-        assert_float_eq!(results.attack_rate, 0.796814, abs <= 1e-5);
+    fn test_vaccine_stockpile_coverage_cap() {
+        let mut params = Parameters::default();
+        params.mitigations.vaccine.enabled = true;
+        params.mitigations.vaccine.coverage_caps = vec![0.05, 0.05];
+        let capped = 0.05 * params.population;
+        let run = |params: Parameters<2>| {
+            let output = SEIRModel::new(params).integrate(300);
+            let doses = total_by_day(&output, &OutputType::DosesAdministered);
+            let stockpile = output.get_vaccine_stockpile().last().unwrap().value;
+            (doses.iter().sum::<f64>(), stockpile)
+        };
+        // The schedule plans all 40M doses, but only the people under the
+        // caps receive one and the rest stay in the stockpile
+        let (doses, stockpile) = run(params.clone());
+        assert_float_eq!(doses, capped, rmax <= 1e-3);
+        assert_float_eq!(stockpile, 40_000_000.0 - capped, rmax <= 1e-3);
+
+        // Second doses only go to the people who received a first dose
+        params.mitigations.vaccine.doses = 2;
+        params.mitigations.vaccine.dose_interval = 21.0;
+        params.mitigations.vaccine.partial_ve_s = vec![0.5];
+        params.mitigations.vaccine.partial_ve_i = vec![0.5];
+        params.mitigations.vaccine.partial_ve_p = vec![0.5];
+        let (doses, stockpile) = run(params);
+        assert!(doses <= 2.0 * capped * (1.0 + 1e-3));
+        assert_float_eq!(stockpile, 40_000_000.0 - doses, rmax <= 1e-6);
     }
 
-    // population <- 3.3e8
-    // SEIRTVModel(
-    //     simulationLength = 300,
-    //     population = population,
-    //     R0 = 2.0,
-    //     latentPeriod = 1.0,
-    //     infectiousPeriod = 3.0,
-    //     seedInfections = 1000.0 / population,
-    //     tolerance = 1e-8,
-    //     VEs = 0.5,
-    //     VEi = 0.5,
-    //     VEp = 0.5,
-    //     vaccineAdministrationRatePerDay = 1e6,
-    //     vaccineAvailabilityByDay = c(2e7),
-    // )
     #[test]
-    fn test_seir_vaccine() {
-        let vaccine_params = VaccineParams {
-            enabled: true,
-            editable: true,
-            doses: 1,
-            start: 0.0,
-            administration_rate: 1_000_000.0,
-            doses_available: 20_000_000.0,
-            ve_s: 0.5,
-            ve_i: 0.5,
-            ve_p: 0.5,
-        };
+    fn test_multi_dose() {
+        let mut params = Parameters::default();
+        params.mitigations.vaccine.enabled = true;
+        let one_dose = SEIRModel::new(params.clone());
+        let one_dose_results = TestResults::new(&one_dose.parameters, &one_dose.integrate(300));
 
-        let model = SEIRModel::new(Parameters {
-            population: 330_000_000.0,
-            population_fractions: Vector1::new(1.0),
-            population_fraction_labels: Vector1::new("All".to_string()),
-            contact_matrix: Matrix1::new(1.0),
-            initial_infections: 1000.0,
-            r0: 2.0,
-            latent_period: 1.0,
-            infectious_period: 3.0,
-            mitigations: MitigationParams {
-                vaccine: vaccine_params,
-                antivirals: MitigationParams::<1>::default().antivirals,
-                community: MitigationParams::<1>::default().community,
-            },
-            fraction_symptomatic: Vector1::new(0.5),
-            fraction_hospitalized: Vector1::new(0.0),
-            hospitalization_delay: 1.0,
-            fraction_dead: Vector1::new(0.0),
-            death_delay: 1.0,
-            p_test_sympto: 0.0,
-            test_sensitivity: 0.90,
-            p_test_forward: 0.90,
-        });
-        let results = TestResults::new(&model.parameters, &model.integrate(300));
-        let expected = 0.7583813;
-        assert_float_eq!(results.attack_rate, expected, abs <= 1e-5);
+        // The second dose uses up vaccine without adding protection
+        params.mitigations.vaccine.doses = 2;
+        params.mitigations.vaccine.dose_interval = 21.0;
+        params.mitigations.vaccine.partial_ve_s = vec![0.5];
+        params.mitigations.vaccine.partial_ve_i = vec![0.5];
+        params.mitigations.vaccine.partial_ve_p = vec![0.5];
+        let two_doses = SEIRModel::new(params.clone());
+        let two_doses_results = TestResults::new(&two_doses.parameters, &two_doses.integrate(300));
+        assert!(two_doses_results.attack_rate > one_dose_results.attack_rate);
+
+        // Even more so when the first dose alone gives no protection
+        params.mitigations.vaccine.partial_ve_s = vec![0.0];
+        params.mitigations.vaccine.partial_ve_i = vec![0.0];
+        params.mitigations.vaccine.partial_ve_p = vec![0.0];
+        let partial = SEIRModel::new(params);
+        let partial_results = TestResults::new(&partial.parameters, &partial.integrate(300));
+        assert!(partial_results.attack_rate > two_doses_results.attack_rate);
     }
 
     #[test]
     fn final_size_relation_with_groups() {
-        let mut params = Parameters::default();
-        params.population = 1.0;
-        params.initial_infections = 1e-8;
-        params.r0 = 2.0;
-        params.latent_period = 1.0;
-        params.infectious_period = 3.0;
+        let params = Parameters {
+            population: 1.0,
+            initial_infections: 1e-8,
+            r0: 2.0,
+            latent_period: 1.0,
+            infectious_period: 3.0,
+            ..Default::default()
+        };
 
         let model = SEIRModel::new(params);
         let output = model.integrate(300);
@@ -531,6 +2804,157 @@ mod test {
         assert!((model.parameters.fraction_dead[1] - ifr[1]).abs() < 1e-5);
     }
 
+    #[test]
+    fn test_hospital_census() {
+        let params = Parameters {
+            r0: 2.0,
+            ..Default::default()
+        };
+        let model = SEIRModel::new(params);
+        let output = model.integrate(400);
+
+        let sum_by_group = |output_type| {
+            output
+                .get_output(&output_type)
+                .iter()
+                .map(|x| DVector::from_vec(x.grouped_values.clone()))
+                .reduce(|acc, elem| acc + elem)
+                .unwrap()
+        };
+        let admissions = sum_by_group(OutputType::HospitalIncidence);
+        let bed_days = sum_by_group(OutputType::HospitalCensus);
+        let icu_bed_days = sum_by_group(OutputType::IcuCensus);
+
+        // Each admission occupies a bed for its mean length of stay
+        let params = &model.parameters;
+        for group in 0..params.n() {
+            let icu = params.fraction_icu[group];
+            let expected_icu = admissions[group] * icu * params.icu_length_of_stay[group];
+            let expected = expected_icu
+                + admissions[group] * (1.0 - icu) * params.hospital_length_of_stay[group];
+            assert_float_eq!(bed_days[group], expected, rmax <= 1e-2);
+            assert_float_eq!(icu_bed_days[group], expected_icu, rmax <= 1e-2);
+            assert!(icu_bed_days[group] < bed_days[group]);
+        }
+
+        // Everyone has been discharged by the end of the epidemic
+        let last = output
+            .get_output(&OutputType::HospitalCensus)
+            .last()
+            .unwrap();
+        assert!(
+            last.grouped_values
+                .iter()
+                .all(|&x| x < 1e-3 * bed_days.max())
+        );
+    }
+
+    #[test]
+    fn test_icu_capacity() {
+        let params = Parameters {
+            r0: 2.0,
+            ..Default::default()
+        };
+        let peak_icu = |output: &ModelOutput| {
+            total_by_day(output, &OutputType::IcuCensus)
+                .into_iter()
+                .fold(0.0, f64::max)
+        };
+        let total_deaths = |output: &ModelOutput| {
+            total_by_day(output, &OutputType::DeathIncidence)
+                .iter()
+                .sum()
+        };
+
+        let unlimited = SEIRModel::new(params.clone()).integrate(400);
+        let unlimited_deaths: f64 = total_deaths(&unlimited);
+        let capacity = 0.5 * peak_icu(&unlimited);
+
+        let limited = SEIRModel::new(Parameters {
+            icu_capacity: Some(capacity),
+            ..params.clone()
+        })
+        .integrate(400);
+        let limited_deaths: f64 = total_deaths(&limited);
+
+        // Occupancy never exceeds the number of beds, and those turned away
+        // are more likely to die
+        assert!(peak_icu(&limited) <= capacity * (1.0 + 1e-3));
+        assert!(limited_deaths > unlimited_deaths);
+
+        // Admissions are unchanged, only the beds they occupy differ
+        let admissions = |output: &ModelOutput| -> f64 {
+            total_by_day(output, &OutputType::HospitalIncidence)
+                .iter()
+                .sum()
+        };
+        assert_float_eq!(admissions(&limited), admissions(&unlimited), rmax <= 1e-6);
+
+        // Those who die when turned away never occupy a ward bed
+        let all_die = SEIRModel::new(Parameters {
+            icu_capacity: Some(capacity),
+            fraction_dead_icu_overflow: vector![1.0, 1.0],
+            ..params
+        })
+        .integrate(400);
+        let ward_bed_days = |output: &ModelOutput| -> f64 {
+            total_by_day(output, &OutputType::HospitalCensus)
+                .iter()
+                .sum()
+        };
+        assert!(ward_bed_days(&all_die) < ward_bed_days(&limited));
+        assert!(
+            total_by_day(&all_die, &OutputType::HospitalCensus)
+                .iter()
+                .all(|&census| census >= 0.0)
+        );
+        assert_float_eq!(admissions(&all_die), admissions(&unlimited), rmax <= 1e-6);
+    }
+
+    #[test]
+    fn final_size_relation_with_susceptibility() {
+        let params = Parameters {
+            population: 1.0,
+            initial_infections: 1e-8,
+            r0: 2.0,
+            latent_period: 1.0,
+            infectious_period: 3.0,
+            relative_susceptibility: Vector2::new(2.0, 0.5),
+            relative_infectiousness: Vector2::new(0.5, 1.5),
+            ..Default::default()
+        };
+        let model = SEIRModel::new(params);
+        let output = model.integrate(300);
+        let incidence_by_group = output
+            .get_output(&OutputType::InfectionIncidence)
+            .iter()
+            .map(|x| DVector::from_vec(x.grouped_values.clone()))
+            .reduce(|acc, elem| acc + elem)
+            .unwrap();
+
+        // Solve the final size relation by fixed-point iteration:
+        // z_i = 1 - exp(-R0 / rho * sigma_i sum_j C_ij tau_j f_j z_j / f_i)
+        let params = &model.parameters;
+        let next_generation = Matrix2::from_diagonal(&params.relative_susceptibility)
+            * params.contact_matrix
+            * Matrix2::from_diagonal(&params.relative_infectiousness);
+        let (eigenvalue, _) = get_dominant_eigendata(&next_generation);
+        let f = &params.population_fractions;
+        let mut z = Vector2::new(0.5, 0.5);
+        for _ in 0..1000 {
+            let pressure =
+                (next_generation * z.component_mul(f)).component_div(f) * (params.r0 / eigenvalue);
+            z = pressure.map(|x| 1.0 - (-x).exp());
+        }
+
+        for group in 0..2 {
+            let attack_rate = incidence_by_group[group] / (f[group] * params.population);
+            assert_float_eq!(attack_rate, z[group], abs <= 1e-4);
+        }
+        // Groups with higher susceptibility are infected more
+        assert!(z[0] > z[1]);
+    }
+
     // population <- 3.3e8
     // model <- SEIRTVModel(
     //     simulationLength = 300,
@@ -552,23 +2976,9 @@ mod test {
     #[test]
     fn test_antiviral() {
         let mut params = Parameters {
-            population: 330_000_000.0,
-            population_fractions: Vector1::new(1.0),
-            population_fraction_labels: Vector1::new("All".to_string()),
-            contact_matrix: Matrix1::new(1.0),
-            initial_infections: 1_000.0,
-            r0: 2.0,
-            latent_period: 1.0,
-            infectious_period: 3.0,
-            mitigations: MitigationParams::default(),
-            fraction_symptomatic: Vector1::new(0.5),
             fraction_hospitalized: Vector1::new(0.1),
-            hospitalization_delay: 1.0,
             fraction_dead: Vector1::new(0.01),
-            death_delay: 1.0,
-            p_test_sympto: 0.0,
-            test_sensitivity: 0.90,
-            p_test_forward: 0.90,
+            ..single_group_params()
         };
         params.mitigations.antivirals = AntiviralsParams {
             enabled: true,
@@ -586,6 +2996,84 @@ mod test {
         assert_float_eq!(results.attack_rate, 0.77889514, abs <= 1e-5);
     }
 
+    #[test]
+    fn test_asymptomatic_infectiousness() {
+        let attack_rate = |params: Parameters<2>| {
+            TestResults::new(&params, &SEIRModel::new(params.clone()).integrate(300)).attack_rate
+        };
+        let params = Parameters {
+            r0: 2.0,
+            ..Default::default()
+        };
+        // R0 accounts for less infectious asymptomatic infections, so that
+        // only their share of transmission changes
+        let baseline = attack_rate(params.clone());
+        let less_infectious = Parameters {
+            asymptomatic_relative_infectiousness: 0.5,
+            ..params.clone()
+        };
+        assert_float_eq!(attack_rate(less_infectious.clone()), baseline, rmax <= 1e-4);
+
+        // Antivirals are only given to symptomatic infections, and do more
+        // when these drive a larger share of transmission
+        let mut antivirals = params.clone();
+        antivirals.mitigations.antivirals.enabled = true;
+        antivirals.mitigations.antivirals.ave_i = 0.5;
+        let all_asymptomatic = |params: &Parameters<2>| Parameters {
+            fraction_symptomatic: vector![0.0, 0.0],
+            ..params.clone()
+        };
+        assert_float_eq!(
+            attack_rate(all_asymptomatic(&antivirals)),
+            attack_rate(all_asymptomatic(&params)),
+            rmax <= 1e-6
+        );
+        let reduction = |params: &Parameters<2>, asymptomatic_relative_infectiousness| {
+            let mut with_antivirals = Parameters {
+                asymptomatic_relative_infectiousness,
+                ..params.clone()
+            };
+            let without = attack_rate(with_antivirals.clone());
+            with_antivirals.mitigations.antivirals = antivirals.mitigations.antivirals.clone();
+            without - attack_rate(with_antivirals)
+        };
+        assert!(reduction(&params, 0.0) > reduction(&params, 1.0));
+        assert!(reduction(&params, 1.0) > 0.0);
+    }
+
+    #[test]
+    fn test_dynamic_matches_static() {
+        let mut mitigated = Parameters::default();
+        mitigated.mitigations.vaccine.enabled = true;
+        mitigated.mitigations.antivirals.enabled = true;
+        mitigated.mitigations.community.enabled = true;
+        let staged = Parameters {
+            latent_stages: 2,
+            infectious_stages: 3,
+            ..mitigated.clone()
+        };
+
+        for params in [Parameters::default(), mitigated, staged] {
+            let export = ParametersExport::from(params);
+            let output =
+                SEIRModel::new(Parameters::<2>::try_from(export.clone()).unwrap()).integrate(300);
+            let doutput = SEIRModelDyn::new(DParameters::try_from(export).unwrap()).integrate(300);
+
+            for output_type in OutputType::iter() {
+                let items = output.get_output(&output_type);
+                let ditems = doutput.get_output(&output_type);
+                assert_eq!(items.len(), ditems.len());
+                for (item, ditem) in items.iter().zip(ditems) {
+                    assert_eq!(item.time, ditem.time);
+                    assert_eq!(item.grouped_values.len(), ditem.grouped_values.len());
+                    for (value, dvalue) in item.grouped_values.iter().zip(&ditem.grouped_values) {
+                        assert_float_eq!(*value, *dvalue, abs <= ATOL, rmax <= RTOL);
+                    }
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_eigen() {
         let x = matrix![1.0, 3.0; 2.0, 4.0];
@@ -593,5 +3081,198 @@ mod test {
         assert!((eval - 5.3722813).abs() < 1e-6);
         assert!((evec[0] - 0.4069297).abs() < 1e-6);
         assert!((evec[1] - 0.5930703).abs() < 1e-6);
+
+        // Contacts only between groups, with eigenvalues of equal modulus
+        let x = matrix![0.0, 2.0; 1.0, 0.0];
+        let (eval, evec) = get_dominant_eigendata(&x);
+        assert_float_eq!(eval, 2.0_f64.sqrt(), abs <= 1e-9);
+        assert_float_eq!(evec[0] / evec[1], 2.0_f64.sqrt(), abs <= 1e-6);
+    }
+
+    #[test]
+    fn test_reproduction_numbers() {
+        let params = Parameters {
+            r0: 2.0,
+            relative_susceptibility: Vector2::new(2.0, 0.5),
+            relative_infectiousness: Vector2::new(0.5, 1.5),
+            ..Default::default()
+        };
+        let model = SEIRModel::new(params.clone());
+        let numbers = model.reproduction_numbers();
+        assert_float_eq!(numbers.r0, 2.0, abs <= 1e-9);
+        // Secondary infections by an infection in each group, from the
+        // columns of the next generation matrix
+        let next_generation = Matrix2::from_diagonal(&params.relative_susceptibility)
+            * params.contact_matrix
+            * Matrix2::from_diagonal(&params.relative_infectiousness);
+        let (eigenvalue, eigenvector) = get_dominant_eigendata(&next_generation);
+        for group in 0..2 {
+            assert_float_eq!(
+                numbers.by_group[group],
+                next_generation.column(group).sum() * 2.0 / eigenvalue,
+                abs <= 1e-9
+            );
+        }
+        // and R0 is their average over the dominant mix of infections
+        assert_float_eq!(
+            numbers.by_group[0] * eigenvector[0] + numbers.by_group[1] * eigenvector[1],
+            2.0,
+            abs <= 1e-6
+        );
+
+        // Seasonal forcing at its peak on the first day
+        let seasonal = SEIRModel::new(Parameters {
+            seasonal_amplitude: 0.2,
+            ..params
+        });
+        assert_float_eq!(seasonal.reproduction_numbers().r0, 2.4, abs <= 1e-9);
+    }
+
+    #[test]
+    fn test_introductions() {
+        // Homogeneous mixing with exponentially distributed infectious
+        // periods, where the extinction probability is 1 / R0
+        let params = Parameters {
+            r0: 2.0,
+            contact_matrix: matrix![1.0, 1.0; 1.0, 1.0],
+            fraction_symptomatic: vector![1.0, 1.0],
+            ..Default::default()
+        };
+        let introductions = SEIRModel::new(params.clone()).introductions();
+        for p_extinction in &introductions.p_extinction {
+            assert_float_eq!(*p_extinction, 0.5, abs <= 1e-9);
+        }
+        // Less variable infectious periods make extinction less likely
+        let stages = 20.0;
+        let fixed = SEIRModel::new(Parameters {
+            infectious_stages: stages as usize,
+            ..params.clone()
+        })
+        .introductions();
+        let q = fixed.p_extinction[0];
+        assert_float_eq!(
+            q,
+            (1.0 + 2.0 * (1.0 - q) / stages).powf(-stages),
+            abs <= 1e-9
+        );
+        assert!(q < 0.5);
+        // Transmission from symptomatic infections only, which infect 4 people
+        // on average: q = 1/2 + 1/2 / (1 + 4 (1 - q))
+        let asymptomatic = SEIRModel::new(Parameters {
+            fraction_symptomatic: vector![0.5, 0.5],
+            asymptomatic_relative_infectiousness: 0.0,
+            ..params.clone()
+        })
+        .introductions();
+        assert_float_eq!(asymptomatic.p_extinction[0], 0.75, abs <= 1e-9);
+
+        // Faster epidemics are established sooner, and declining ones never
+        let days = |r0| {
+            SEIRModel::new(Parameters {
+                r0,
+                ..params.clone()
+            })
+            .introductions()
+            .days_to_establishment[0]
+        };
+        assert!(days(1.5) > days(2.0) && days(2.0) > days(3.0) && days(3.0) > 0.0);
+        assert_eq!(days(0.9), f64::INFINITY);
+
+        // Introductions into the group causing more infections die out less
+        let introductions = SEIRModel::new(Parameters {
+            r0: 1.5,
+            ..Default::default()
+        })
+        .introductions();
+        assert!(introductions.p_extinction[0] < introductions.p_extinction[1]);
+        // Lineages that survive are established at similar times, whatever
+        // their group, since surviving is less of a feat in the first one
+        assert!(
+            introductions
+                .days_to_establishment
+                .iter()
+                .all(|days| days.is_finite() && *days > 0.0)
+        );
+    }
+
+    #[test]
+    fn test_r0_growth_rate() {
+        // With unequal group sizes, susceptibilities and infectiousnesses,
+        // infections initially grow at the rate given by R0. With single-stage
+        // latent and infectious periods, R0 = (1 + r T_E)(1 + r T_I).
+        let params = Parameters {
+            population: 1e12,
+            initial_infections: 1.0,
+            r0: 2.0,
+            latent_period: 1.0,
+            infectious_period: 2.5,
+            population_fractions: vector![0.1, 0.9],
+            relative_susceptibility: Vector2::new(2.0, 0.5),
+            relative_infectiousness: Vector2::new(0.5, 1.5),
+            ..Default::default()
+        };
+        let output = SEIRModel::new(params.clone()).integrate(90);
+        let incidence = total_by_day(&output, &OutputType::InfectionIncidence);
+        let growth_rate = (incidence[80] / incidence[60]).ln() / 20.0;
+        let r0 = (1.0 + growth_rate * params.latent_period)
+            * (1.0 + growth_rate * params.infectious_period);
+        assert_float_eq!(r0, params.r0, rmax <= 5e-3);
+
+        // and at the rate converted from R0 with Erlang distributed periods
+        let staged = Parameters {
+            latent_stages: 2,
+            infectious_stages: 3,
+            ..params
+        };
+        let output = SEIRModel::new(staged.clone()).integrate(60);
+        let incidence = total_by_day(&output, &OutputType::InfectionIncidence);
+        let growth_rate = (incidence[50] / incidence[30]).ln() / 20.0;
+        assert_float_eq!(growth_rate, staged.growth_rate(), rmax <= 5e-3);
+    }
+
+    #[test]
+    fn test_rt() {
+        let rt = |output: &ModelOutput| -> Vec<f64> {
+            output.get_rt().iter().map(|item| item.value).collect()
+        };
+        // Symmetric groups, so that Rt = R0 S / N
+        let params = Parameters {
+            population: 1.0,
+            initial_infections: 1e-6,
+            r0: 2.0,
+            population_fractions: vector![0.5, 0.5],
+            contact_matrix: Matrix2::from_element(1.0),
+            ..Default::default()
+        };
+        let model = SEIRModel::new(params.clone());
+        let output = model.integrate(300);
+        let unmitigated = rt(&output);
+        let infections = total_by_day(&output, &OutputType::InfectionIncidence);
+        let attack_rate = infections.iter().sum::<f64>() - params.initial_infections;
+        assert_float_eq!(unmitigated[0], 2.0, abs <= 1e-3);
+        assert_float_eq!(
+            unmitigated[299],
+            2.0 * (1.0 - params.initial_infections - attack_rate),
+            abs <= 1e-4
+        );
+        // Rt crosses 1 before the peak of incidence
+        let crossing = unmitigated.iter().position(|&rt| rt < 1.0).unwrap();
+        assert!(crossing as f64 <= peak_day(&output));
+
+        // Vaccines and community mitigations bring Rt down
+        let mut mitigated = params.clone();
+        mitigated.mitigations.vaccine.enabled = true;
+        mitigated.mitigations.vaccine.start = 0.0;
+        mitigated.mitigations.vaccine.doses_available = 0.2;
+        mitigated.mitigations.vaccine.administration_rate = 0.01;
+        let vaccinated = rt(&SEIRModel::new(mitigated.clone()).integrate(30));
+        assert!(vaccinated[29] < unmitigated[29]);
+        mitigated.mitigations.vaccine.enabled = false;
+        mitigated.mitigations.community.enabled = true;
+        mitigated.mitigations.community.periods[0].start = 10.0;
+        mitigated.mitigations.community.periods[0].effectiveness = Matrix2::from_element(0.5);
+        let community = rt(&SEIRModel::new(mitigated).integrate(30));
+        assert_float_eq!(community[5], unmitigated[5], abs <= 1e-9);
+        assert_float_eq!(community[15], unmitigated[15] * 0.5, rmax <= 0.05);
     }
 }
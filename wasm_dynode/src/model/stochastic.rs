@@ -0,0 +1,604 @@
+use super::{Flows, GenericSEIRModel, SEIRSystem, State};
+use crate::{
+    DynodeModel, GenericParameters, Introductions, ModelOutput, ReproductionNumbers,
+    StochasticParams,
+};
+use nalgebra::{Const, DefaultAllocator, Dim, Dyn, OVector, allocator::Allocator};
+use oorandom::Rand64;
+
+/// The SEIR model run as a stochastic process, with the same compartments and
+/// transitions as [`GenericSEIRModel`] but whole numbers of people. Small
+/// populations are simulated one event at a time (Gillespie's direct method),
+/// and larger ones by tau-leaping. Each run of the ensemble is seeded from
+/// the parameters, so that ensembles can be reproduced.
+pub struct GenericStochasticSEIRModel<D: Dim>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    model: GenericSEIRModel<D>,
+    settings: StochasticParams,
+}
+
+/// Stochastic SEIR model for a compile-time number of groups `N`
+pub type StochasticSEIRModel<const N: usize> = GenericStochasticSEIRModel<Const<N>>;
+
+/// Stochastic SEIR model for a number of groups only known at runtime
+pub type StochasticSEIRModelDyn = GenericStochasticSEIRModel<Dyn>;
+
+// Populations up to this size are simulated event by event
+const MAX_EXACT_POPULATION: f64 = 10_000.0;
+
+// Number of tau-leaping steps per day
+const LEAPS_PER_DAY: usize = 10;
+
+// Poisson draws with larger means use a normal approximation
+const MAX_EXACT_POISSON_MEAN: f64 = 30.0;
+
+// Quantiles of the band reported around the median of the ensemble
+const BAND_QUANTILES: (f64, f64) = (0.05, 0.95);
+
+impl Default for StochasticParams {
+    fn default() -> Self {
+        StochasticParams { seed: 0, runs: 50 }
+    }
+}
+
+/// Flow of people between compartments, as recorded from the system, so that
+/// only its rate needs updating as the state changes
+struct Transition<D: Dim>
+where
+    DefaultAllocator: Allocator<D>,
+{
+    from: Option<usize>,
+    to: Vec<usize>,
+    rate: OVector<f64, D>,
+    outcomes: Vec<(usize, OVector<f64, D>)>,
+}
+
+impl<D: Dim> Flows<D> for Vec<Transition<D>>
+where
+    DefaultAllocator: Allocator<D>,
+{
+    fn flow_with_outcomes(
+        &mut self,
+        from: Option<usize>,
+        to: &[usize],
+        rate: OVector<f64, D>,
+        outcomes: &[(usize, &OVector<f64, D>)],
+    ) {
+        self.push(Transition {
+            from,
+            to: to.to_vec(),
+            rate,
+            outcomes: outcomes
+                .iter()
+                .map(|(outcome, fraction)| (*outcome, (*fraction).clone()))
+                .collect(),
+        });
+    }
+}
+
+/// Updates the rates of recorded transitions in place, since the system
+/// always gives the same flows in the same order
+struct Rates<'a, D: Dim>
+where
+    DefaultAllocator: Allocator<D>,
+{
+    transitions: &'a mut [Transition<D>],
+    next: usize,
+}
+
+impl<D: Dim> Flows<D> for Rates<'_, D>
+where
+    DefaultAllocator: Allocator<D>,
+{
+    fn flow_with_outcomes(
+        &mut self,
+        from: Option<usize>,
+        _to: &[usize],
+        rate: OVector<f64, D>,
+        _outcomes: &[(usize, &OVector<f64, D>)],
+    ) {
+        let transition = &mut self.transitions[self.next];
+        debug_assert_eq!(transition.from, from);
+        transition.rate = rate;
+        self.next += 1;
+    }
+}
+
+/// Update the rates of `transitions` on day `x` in state `state`
+fn update_rates<D: Dim>(
+    system: &SEIRSystem<D>,
+    x: f64,
+    state: &State,
+    transitions: &mut [Transition<D>],
+) where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    system.transitions(
+        x,
+        state,
+        &mut Rates {
+            transitions,
+            next: 0,
+        },
+    );
+}
+
+impl<D: Dim> GenericStochasticSEIRModel<D>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    pub fn new(parameters: GenericParameters<D>) -> Self {
+        let settings = parameters.stochastic.unwrap_or_default();
+        Self {
+            model: GenericSEIRModel::new(parameters),
+            settings,
+        }
+    }
+
+    /// Outputs of every run of the ensemble
+    pub fn ensemble(&self, days: usize) -> Vec<ModelOutput> {
+        (0..self.settings.runs)
+            .map(|run| {
+                let mut rng = Rand64::new(((self.settings.seed as u128) << 64) | run as u128);
+                self.run(days, &mut rng)
+            })
+            .collect()
+    }
+
+    /// Initial state of the ODE, with compartments rounded up or down at
+    /// random to whole people, keeping their expected size
+    fn initial_state(&self, rng: &mut Rand64) -> State {
+        self.model
+            .initial_state()
+            .map(|people| people.floor() + bernoulli(rng, people.fract()))
+    }
+
+    fn run(&self, days: usize, rng: &mut Rand64) -> ModelOutput {
+        let model = &self.model;
+        let mut state = self.initial_state(rng);
+        let mut windows = model.initial_community_windows();
+        let exact = model.parameters.population <= MAX_EXACT_POPULATION;
+        let mut times = vec![0.0];
+        let mut states = vec![state.clone()];
+        // The flows are the same throughout, only their rates change
+        let mut transitions = Vec::new();
+        SEIRSystem {
+            model,
+            community_windows: &windows,
+        }
+        .transitions(0.0, &state, &mut transitions);
+        for day in 0..days {
+            let system = SEIRSystem {
+                model,
+                community_windows: &windows,
+            };
+            if exact {
+                simulate_day(&system, day as f64, &mut state, &mut transitions, rng);
+            } else {
+                leap_day(&system, day as f64, &mut state, &mut transitions, rng);
+            }
+            if let Some(infections) = model.variant_introduction(day + 1) {
+                let infections = infections
+                    .map(|infections| infections.floor() + bernoulli(rng, infections.fract()));
+                model.infect_with_variant(&infections, &mut state);
+            }
+            let end = (day + 1) as f64;
+            model.update_community_windows(&mut windows, end, &states[day], &state);
+            times.push(end);
+            states.push(state.clone());
+        }
+        model.output(&times, &states, &windows)
+    }
+}
+
+impl<D: Dim> DynodeModel for GenericStochasticSEIRModel<D>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    fn integrate(&self, days: usize) -> ModelOutput {
+        let (lower, upper) = BAND_QUANTILES;
+        ModelOutput::from_ensemble(&self.ensemble(days), lower, upper)
+    }
+
+    fn reproduction_numbers(&self) -> ReproductionNumbers {
+        self.model.reproduction_numbers()
+    }
+
+    fn introductions(&self) -> Introductions {
+        self.model.introductions()
+    }
+}
+
+/// Simulate every event from day `start` to the next, each at a time drawn
+/// from the total rate of all transitions, whose rates are updated after
+/// every event. Since waiting times are memoryless, the event that would
+/// happen after the end of the day is dropped.
+fn simulate_day<D: Dim>(
+    system: &SEIRSystem<D>,
+    start: f64,
+    state: &mut State,
+    transitions: &mut [Transition<D>],
+    rng: &mut Rand64,
+) where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    let end = start + 1.0;
+    let mut t = start;
+    loop {
+        update_rates(system, t, state, transitions);
+        let total: f64 = transitions
+            .iter()
+            .flat_map(|transition| transition.rate.iter())
+            .map(|rate| rate.max(0.0))
+            .sum();
+        if total <= 0.0 {
+            return;
+        }
+        t += exponential(rng, total);
+        if t >= end {
+            return;
+        }
+        let mut target = rng.rand_float() * total;
+        'pick: for transition in transitions.iter() {
+            for (group, rate) in transition.rate.iter().enumerate() {
+                let rate = rate.max(0.0);
+                if target < rate {
+                    apply(transition, group, 1.0, state, rng);
+                    break 'pick;
+                }
+                target -= rate;
+            }
+        }
+    }
+}
+
+/// Simulate day `start` in steps of fixed length, over which the number of
+/// times each transition happens is drawn from the rates at the start of
+/// the step
+fn leap_day<D: Dim>(
+    system: &SEIRSystem<D>,
+    start: f64,
+    state: &mut State,
+    transitions: &mut [Transition<D>],
+    rng: &mut Rand64,
+) where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    let tau = 1.0 / LEAPS_PER_DAY as f64;
+    for step in 0..LEAPS_PER_DAY {
+        let t = start + step as f64 * tau;
+        update_rates(system, t, state, transitions);
+        for transition in transitions.iter() {
+            for (group, rate) in transition.rate.iter().enumerate() {
+                let events = poisson(rng, rate.max(0.0) * tau);
+                apply(transition, group, events, state, rng);
+            }
+        }
+    }
+}
+
+/// Move people of a group along a transition, no more than there are in the
+/// compartment they leave, and draw which of them go on to each outcome
+fn apply<D: Dim>(
+    transition: &Transition<D>,
+    group: usize,
+    events: f64,
+    state: &mut State,
+    rng: &mut Rand64,
+) where
+    DefaultAllocator: Allocator<D>,
+{
+    let events = match transition.from {
+        Some(from) => {
+            let events = events.min(state[from + group]);
+            state[from + group] -= events;
+            events
+        }
+        None => events,
+    };
+    for to in &transition.to {
+        state[to + group] += events;
+    }
+    for (outcome, fraction) in &transition.outcomes {
+        state[outcome + group] += binomial(rng, events, fraction[group]);
+    }
+}
+
+/// One with probability `p`, zero otherwise
+fn bernoulli(rng: &mut Rand64, p: f64) -> f64 {
+    if rng.rand_float() < p { 1.0 } else { 0.0 }
+}
+
+/// Waiting time until the next event of a Poisson process
+fn exponential(rng: &mut Rand64, rate: f64) -> f64 {
+    -(1.0 - rng.rand_float()).ln() / rate
+}
+
+/// Number of events of a Poisson process with mean `mean`: by multiplying
+/// uniform draws (Knuth) for small means, and from a normal approximation
+/// for large ones
+fn poisson(rng: &mut Rand64, mean: f64) -> f64 {
+    if mean <= 0.0 {
+        0.0
+    } else if mean < MAX_EXACT_POISSON_MEAN {
+        let limit = (-mean).exp();
+        let mut product = rng.rand_float();
+        let mut count = 0.0;
+        while product > limit {
+            count += 1.0;
+            product *= rng.rand_float();
+        }
+        count
+    } else {
+        (mean + mean.sqrt() * standard_normal(rng)).round().max(0.0)
+    }
+}
+
+/// Number of successes in `trials` independent trials with probability `p`:
+/// by inverting the distribution function when few successes or failures
+/// are expected, and from a normal approximation otherwise
+fn binomial(rng: &mut Rand64, trials: f64, p: f64) -> f64 {
+    let p = p.clamp(0.0, 1.0);
+    if trials <= 0.0 || p == 0.0 {
+        return 0.0;
+    }
+    if p > 0.5 {
+        return trials - binomial(rng, trials, 1.0 - p);
+    }
+    if trials * p < MAX_EXACT_POISSON_MEAN {
+        let odds = p / (1.0 - p);
+        let mut probability = (1.0 - p).powf(trials);
+        let mut target = rng.rand_float();
+        let mut count = 0.0;
+        while target > probability && count < trials {
+            target -= probability;
+            count += 1.0;
+            probability *= odds * (trials - count + 1.0) / count;
+        }
+        count
+    } else {
+        let mean = trials * p;
+        (mean + (mean * (1.0 - p)).sqrt() * standard_normal(rng))
+            .round()
+            .clamp(0.0, trials)
+    }
+}
+
+/// Standard normal draw (Box-Muller)
+fn standard_normal(rng: &mut Rand64) -> f64 {
+    let radius = (-2.0 * (1.0 - rng.rand_float()).ln()).sqrt();
+    radius * (2.0 * std::f64::consts::PI * rng.rand_float()).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OutputType, Parameters, SEIRModel, VariantParams};
+    use float_eq::assert_float_eq;
+    use nalgebra::vector;
+
+    fn total_infections(output: &ModelOutput) -> f64 {
+        output
+            .get_output(&OutputType::InfectionIncidence)
+            .iter()
+            .map(|item| item.grouped_values.iter().sum::<f64>())
+            .sum()
+    }
+
+    #[test]
+    fn test_poisson() {
+        let mut rng = Rand64::new(1);
+        for mean in [0.5, 10.0, 1000.0] {
+            let draws: Vec<_> = (0..10_000).map(|_| poisson(&mut rng, mean)).collect();
+            let sample_mean = draws.iter().sum::<f64>() / draws.len() as f64;
+            let sample_variance = draws
+                .iter()
+                .map(|draw| (draw - sample_mean).powi(2))
+                .sum::<f64>()
+                / (draws.len() - 1) as f64;
+            assert!(draws.iter().all(|draw| draw.fract() == 0.0 && *draw >= 0.0));
+            assert_float_eq!(sample_mean, mean, rmax <= 0.05);
+            assert_float_eq!(sample_variance, mean, rmax <= 0.1);
+        }
+    }
+
+    #[test]
+    fn test_binomial() {
+        let mut rng = Rand64::new(1);
+        for (trials, p) in [(10.0, 0.3), (1_000.0, 0.01), (1_000.0, 0.4), (50.0, 0.9)] {
+            let draws: Vec<_> = (0..10_000).map(|_| binomial(&mut rng, trials, p)).collect();
+            let sample_mean = draws.iter().sum::<f64>() / draws.len() as f64;
+            let sample_variance = draws
+                .iter()
+                .map(|draw| (draw - sample_mean).powi(2))
+                .sum::<f64>()
+                / (draws.len() - 1) as f64;
+            assert!(
+                draws
+                    .iter()
+                    .all(|draw| draw.fract() == 0.0 && (0.0..=trials).contains(draw))
+            );
+            assert_float_eq!(sample_mean, trials * p, rmax <= 0.05);
+            assert_float_eq!(sample_variance, trials * p * (1.0 - p), rmax <= 0.1);
+        }
+        assert_eq!(binomial(&mut rng, 7.0, 1.0), 7.0);
+        assert_eq!(binomial(&mut rng, 7.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_reproducible() {
+        let parameters = Parameters {
+            population: 1_000.0,
+            initial_infections: 5.0,
+            stochastic: Some(StochasticParams { seed: 7, runs: 3 }),
+            ..Default::default()
+        };
+        let infections = |parameters: &Parameters<2>| -> Vec<f64> {
+            StochasticSEIRModel::new(parameters.clone())
+                .ensemble(100)
+                .iter()
+                .map(total_infections)
+                .collect()
+        };
+        let first = infections(&parameters);
+        assert_eq!(first, infections(&parameters));
+        // Runs differ from each other and with the seed
+        assert!(first.windows(2).any(|runs| runs[0] != runs[1]));
+        let reseeded = Parameters {
+            stochastic: Some(StochasticParams { seed: 8, runs: 3 }),
+            ..parameters
+        };
+        assert_ne!(first, infections(&reseeded));
+    }
+
+    #[test]
+    fn test_conservation() {
+        // People only move between compartments, one at a time
+        let parameters = Parameters {
+            population: 1_000.0,
+            initial_infections: 10.0,
+            latent_stages: 2,
+            ..Default::default()
+        };
+        let model = StochasticSEIRModel::new(parameters);
+        let mut rng = Rand64::new(3);
+        let mut state = model.initial_state(&mut rng);
+        // Disease states come before the counters
+        let people = |state: &State| state.rows(0, model.model.layout.v_cum_slot(0)).sum();
+        let windows = model.model.initial_community_windows();
+        let system = SEIRSystem {
+            model: &model.model,
+            community_windows: &windows,
+        };
+        let mut transitions = Vec::new();
+        system.transitions(0.0, &state, &mut transitions);
+        let before = people(&state);
+        for day in 0..50 {
+            simulate_day(&system, day as f64, &mut state, &mut transitions, &mut rng);
+            assert!(
+                state
+                    .iter()
+                    .all(|people| *people >= 0.0 && people.fract() == 0.0)
+            );
+        }
+        assert_eq!(people(&state), before);
+    }
+
+    #[test]
+    fn test_outcomes_follow_infections() {
+        // Only people who become infectious are hospitalized or die
+        let parameters = Parameters {
+            population: 1_000.0,
+            initial_infections: 10.0,
+            fraction_hospitalized: vector![1.0, 0.5],
+            fraction_dead: vector![0.0, 0.5],
+            ..Default::default()
+        };
+        let model = StochasticSEIRModel::new(parameters);
+        let layout = &model.model.layout;
+        let mut rng = Rand64::new(5);
+        let mut state = model.initial_state(&mut rng);
+        let windows = model.model.initial_community_windows();
+        let system = SEIRSystem {
+            model: &model.model,
+            community_windows: &windows,
+        };
+        let mut transitions = Vec::new();
+        system.transitions(0.0, &state, &mut transitions);
+        let hospitalized = |state: &State, group| {
+            state[layout.pre_h_slot(0) + group] + state[layout.h_cum_slot(0) + group]
+        };
+        let dead = |state: &State, group| {
+            state[layout.pre_d_slot(0) + group] + state[layout.d_cum_slot(0) + group]
+        };
+        for day in 0..100 {
+            simulate_day(&system, day as f64, &mut state, &mut transitions, &mut rng);
+            let infections = |group| state[layout.i_cum_slot(0) + group];
+            assert_eq!(hospitalized(&state, 0), infections(0));
+            assert_eq!(dead(&state, 0), 0.0);
+            assert!(hospitalized(&state, 1) <= infections(1));
+            assert!(dead(&state, 1) <= infections(1));
+        }
+        assert!(hospitalized(&state, 1) > 0.0 && dead(&state, 1) > 0.0);
+    }
+
+    #[test]
+    fn test_variant_introduction() {
+        let parameters = Parameters {
+            population: 1_000.0,
+            initial_infections: 5.0,
+            variant: Some(VariantParams {
+                relative_transmissibility: 1.2,
+                introduction_day: 10,
+                introduced_infections: 5.0,
+                vaccine_escape: 0.0,
+                infection_escape: 0.0,
+            }),
+            stochastic: Some(StochasticParams { seed: 2, runs: 5 }),
+            ..Default::default()
+        };
+        for run in StochasticSEIRModel::new(parameters).ensemble(100) {
+            let variant = run.get_output(&OutputType::VariantInfectionIncidence);
+            let daily: Vec<f64> = variant
+                .iter()
+                .map(|item| item.grouped_values.iter().sum())
+                .collect();
+            assert!(daily[..10].iter().all(|&infections| infections == 0.0));
+            // The introduced infections all become infectious
+            assert!(daily.iter().sum::<f64>() >= 5.0);
+        }
+    }
+
+    #[test]
+    fn test_extinction() {
+        // A single introduction often dies out by chance, and otherwise takes off
+        let parameters = Parameters {
+            population: 2_000.0,
+            initial_infections: 1.0,
+            r0: 2.0,
+            stochastic: Some(StochasticParams { seed: 1, runs: 40 }),
+            ..Default::default()
+        };
+        let ensemble = StochasticSEIRModel::new(parameters).ensemble(200);
+        let extinct = ensemble
+            .iter()
+            .filter(|run| total_infections(run) < 20.0)
+            .count();
+        assert!(extinct > 5 && extinct < 35, "{extinct} runs died out");
+        assert!(
+            ensemble
+                .iter()
+                .all(|run| total_infections(run) < 20.0 || total_infections(run) > 1_000.0)
+        );
+    }
+
+    #[test]
+    fn test_tau_leap_matches_ode() {
+        let parameters = Parameters {
+            population: 1_000_000.0,
+            stochastic: Some(StochasticParams { seed: 0, runs: 5 }),
+            ..Default::default()
+        };
+        let ode = total_infections(&SEIRModel::new(parameters.clone()).integrate(300));
+        let model = StochasticSEIRModel::new(parameters);
+        let output = model.integrate(300);
+        assert_float_eq!(total_infections(&output), ode, rmax <= 0.01);
+
+        // The median lies within the band
+        for output_type in [OutputType::InfectionIncidence, OutputType::HospitalCensus] {
+            for (item, band) in output
+                .get_output(&output_type)
+                .iter()
+                .zip(output.get_band(&output_type))
+            {
+                for ((median, lower), upper) in
+                    item.grouped_values.iter().zip(&band.lower).zip(&band.upper)
+                {
+                    assert!(lower <= median && median <= upper);
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{Parameters, ParametersExport, SEIRModel};
+use crate::{DParameters, ParametersExport, SEIRModelDyn, StochasticSEIRModelDyn};
 use serde::{Deserialize, Serialize};
 use serde_wasm_bindgen::from_value;
 use std::{any::Any, collections::HashMap};
@@ -18,9 +18,20 @@ pub enum MitigationType {
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub enum OutputType {
     InfectionIncidence,
+    // infections with each strain, which add up to `InfectionIncidence`
+    FirstStrainInfectionIncidence,
+    VariantInfectionIncidence,
+    ReinfectionIncidence,
     SymptomaticIncidence,
     HospitalIncidence,
     DeathIncidence,
+    HospitalCensus,
+    IcuCensus,
+    DosesAdministered,
+    DosesToSusceptible,
+    DosesToExposed,
+    DosesToInfectious,
+    DosesToRecovered,
 }
 
 #[derive(Tsify, Debug, Clone, Serialize, Deserialize)]
@@ -37,20 +48,94 @@ pub struct OutputItemSingle {
     pub(crate) value: f64,
 }
 
+/// Values of each group between a lower and an upper quantile of an ensemble
+/// of stochastic runs
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct OutputBandGrouped {
+    pub(crate) time: f64,
+    pub(crate) lower: Vec<f64>,
+    pub(crate) upper: Vec<f64>,
+}
+
+/// Total over all groups between a lower and an upper quantile of an ensemble
+/// of stochastic runs. Quantiles of the total are not the sums of the
+/// quantiles of each group.
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct OutputBandSingle {
+    pub(crate) time: f64,
+    pub(crate) lower: f64,
+    pub(crate) upper: f64,
+}
+
+/// Days on which a community mitigation period was put in effect and lifted,
+/// if it was lifted during the run
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CommunityMitigationWindow {
+    pub(crate) period: usize,
+    pub(crate) start: f64,
+    pub(crate) end: Option<f64>,
+}
+
+/// Reproduction numbers of a fully susceptible population on the first day:
+/// the dominant eigenvalue of the next generation matrix, and the expected
+/// number of people infected by an infection in each group
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ReproductionNumbers {
+    pub r0: f64,
+    pub by_group: Vec<f64>,
+}
+
+/// Fate of one infection introduced into a fully susceptible population on
+/// the first day, by the group of the introduced infection: the probability
+/// that all its chains of transmission die out, and the expected number of
+/// days until transmission is sustained when they do not (infinite if it
+/// never is)
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct Introductions {
+    pub p_extinction: Vec<f64>,
+    pub days_to_establishment: Vec<f64>,
+}
+
 pub struct ModelOutput {
     output: HashMap<OutputType, Vec<OutputItemGrouped>>,
+    // Spread of each output over an ensemble, empty for a single run
+    bands: HashMap<OutputType, Vec<OutputBandGrouped>>,
+    total_bands: HashMap<OutputType, Vec<OutputBandSingle>>,
     p_detect: Vec<OutputItemSingle>,
+    vaccine_stockpile: Vec<OutputItemSingle>,
+    community_mitigations: Vec<CommunityMitigationWindow>,
+    rt: Vec<OutputItemSingle>,
+}
+
+impl Default for ModelOutput {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ModelOutput {
     pub fn new() -> Self {
         let mut output = HashMap::new();
+        let mut bands = HashMap::new();
+        let mut total_bands = HashMap::new();
         OutputType::iter().for_each(|output_type| {
-            output.insert(output_type, Vec::new());
+            output.insert(output_type.clone(), Vec::new());
+            bands.insert(output_type.clone(), Vec::new());
+            total_bands.insert(output_type, Vec::new());
         });
         Self {
             output,
+            bands,
+            total_bands,
             p_detect: Vec::new(),
+            vaccine_stockpile: Vec::new(),
+            community_mitigations: Vec::new(),
+            rt: Vec::new(),
         }
     }
     pub fn get_output(&self, output_type: &OutputType) -> &Vec<OutputItemGrouped> {
@@ -67,9 +152,42 @@ impl ModelOutput {
                 grouped_values,
             });
     }
+    pub fn get_band(&self, output_type: &OutputType) -> &Vec<OutputBandGrouped> {
+        self.bands.get(output_type).expect("Unexpected output type")
+    }
+    fn add_band(&mut self, output_type: &OutputType, time: f64, lower: Vec<f64>, upper: Vec<f64>) {
+        self.bands
+            .get_mut(output_type)
+            .expect("Unexpected output type")
+            .push(OutputBandGrouped { time, lower, upper });
+    }
+    pub fn get_total_band(&self, output_type: &OutputType) -> &Vec<OutputBandSingle> {
+        self.total_bands
+            .get(output_type)
+            .expect("Unexpected output type")
+    }
+    fn add_total_band(&mut self, output_type: &OutputType, time: f64, lower: f64, upper: f64) {
+        self.total_bands
+            .get_mut(output_type)
+            .expect("Unexpected output type")
+            .push(OutputBandSingle { time, lower, upper });
+    }
     pub fn add_infection_incidence(&mut self, time: f64, grouped_values: Vec<f64>) {
         self.add_output(&OutputType::InfectionIncidence, time, grouped_values);
     }
+    pub fn add_first_strain_infection_incidence(&mut self, time: f64, grouped_values: Vec<f64>) {
+        self.add_output(
+            &OutputType::FirstStrainInfectionIncidence,
+            time,
+            grouped_values,
+        );
+    }
+    pub fn add_variant_infection_incidence(&mut self, time: f64, grouped_values: Vec<f64>) {
+        self.add_output(&OutputType::VariantInfectionIncidence, time, grouped_values);
+    }
+    pub fn add_reinfection_incidence(&mut self, time: f64, grouped_values: Vec<f64>) {
+        self.add_output(&OutputType::ReinfectionIncidence, time, grouped_values);
+    }
     pub fn add_symptomatic_incidence(&mut self, time: f64, grouped_values: Vec<f64>) {
         self.add_output(&OutputType::SymptomaticIncidence, time, grouped_values);
     }
@@ -79,16 +197,139 @@ impl ModelOutput {
     pub fn add_death_incidence(&mut self, time: f64, grouped_values: Vec<f64>) {
         self.add_output(&OutputType::DeathIncidence, time, grouped_values);
     }
+    pub fn add_hospital_census(&mut self, time: f64, grouped_values: Vec<f64>) {
+        self.add_output(&OutputType::HospitalCensus, time, grouped_values);
+    }
+    pub fn add_icu_census(&mut self, time: f64, grouped_values: Vec<f64>) {
+        self.add_output(&OutputType::IcuCensus, time, grouped_values);
+    }
+    pub fn add_doses_administered(&mut self, time: f64, grouped_values: Vec<f64>) {
+        self.add_output(&OutputType::DosesAdministered, time, grouped_values);
+    }
+    pub fn add_doses_by_state(&mut self, time: f64, grouped_values_by_state: [Vec<f64>; 4]) {
+        let [susceptible, exposed, infectious, recovered] = grouped_values_by_state;
+        self.add_output(&OutputType::DosesToSusceptible, time, susceptible);
+        self.add_output(&OutputType::DosesToExposed, time, exposed);
+        self.add_output(&OutputType::DosesToInfectious, time, infectious);
+        self.add_output(&OutputType::DosesToRecovered, time, recovered);
+    }
+    pub fn get_p_detect(&self) -> &Vec<OutputItemSingle> {
+        &self.p_detect
+    }
     pub fn add_p_detect(&mut self, time: f64, value: f64) {
         self.p_detect.push(OutputItemSingle { time, value });
     }
+    pub fn get_vaccine_stockpile(&self) -> &Vec<OutputItemSingle> {
+        &self.vaccine_stockpile
+    }
+    pub fn add_vaccine_stockpile(&mut self, time: f64, value: f64) {
+        self.vaccine_stockpile
+            .push(OutputItemSingle { time, value });
+    }
+    pub fn get_rt(&self) -> &Vec<OutputItemSingle> {
+        &self.rt
+    }
+    pub fn add_rt(&mut self, time: f64, value: f64) {
+        self.rt.push(OutputItemSingle { time, value });
+    }
+    pub fn get_community_mitigations(&self) -> &Vec<CommunityMitigationWindow> {
+        &self.community_mitigations
+    }
+    pub fn add_community_mitigation(&mut self, period: usize, start: f64, end: Option<f64>) {
+        self.community_mitigations
+            .push(CommunityMitigationWindow { period, start, end });
+    }
+    /// Summary of an ensemble of runs: the median of every output on each
+    /// day, along with the band between the `lower` and `upper` quantiles of
+    /// the grouped outputs and of their totals over all groups. Community
+    /// mitigations are those of the run with
+    /// the median number of infections, as they differ between runs.
+    pub fn from_ensemble(ensemble: &[ModelOutput], lower: f64, upper: f64) -> Self {
+        let mut output = Self::new();
+        let Some(first) = ensemble.first() else {
+            return output;
+        };
+        for output_type in OutputType::iter() {
+            for (day, item) in first.get_output(&output_type).iter().enumerate() {
+                let quantiles = |q: f64| -> Vec<f64> {
+                    (0..item.grouped_values.len())
+                        .map(|group| {
+                            quantile(
+                                ensemble.iter().map(|run| {
+                                    run.get_output(&output_type)[day].grouped_values[group]
+                                }),
+                                q,
+                            )
+                        })
+                        .collect()
+                };
+                let total_quantile = |q: f64| {
+                    quantile(
+                        ensemble.iter().map(|run| {
+                            run.get_output(&output_type)[day]
+                                .grouped_values
+                                .iter()
+                                .sum()
+                        }),
+                        q,
+                    )
+                };
+                output.add_output(&output_type, item.time, quantiles(0.5));
+                output.add_band(&output_type, item.time, quantiles(lower), quantiles(upper));
+                output.add_total_band(
+                    &output_type,
+                    item.time,
+                    total_quantile(lower),
+                    total_quantile(upper),
+                );
+            }
+        }
+        let median = |items: fn(&ModelOutput) -> &Vec<OutputItemSingle>| -> Vec<OutputItemSingle> {
+            items(first)
+                .iter()
+                .enumerate()
+                .map(|(day, item)| OutputItemSingle {
+                    time: item.time,
+                    value: quantile(ensemble.iter().map(|run| items(run)[day].value), 0.5),
+                })
+                .collect()
+        };
+        output.p_detect = median(ModelOutput::get_p_detect);
+        output.vaccine_stockpile = median(ModelOutput::get_vaccine_stockpile);
+        output.rt = median(ModelOutput::get_rt);
+        let infections = |run: &ModelOutput| -> f64 {
+            run.get_output(&OutputType::InfectionIncidence)
+                .iter()
+                .map(|item| item.grouped_values.iter().sum::<f64>())
+                .sum()
+        };
+        let mut runs: Vec<_> = ensemble.iter().collect();
+        runs.sort_by(|a, b| infections(a).total_cmp(&infections(b)));
+        output.community_mitigations = runs[runs.len() / 2].community_mitigations.clone();
+        output
+    }
+}
+
+/// Quantile `q` of some values, interpolated linearly between order statistics
+fn quantile(values: impl Iterator<Item = f64>, q: f64) -> f64 {
+    let mut values: Vec<_> = values.collect();
+    values.sort_by(f64::total_cmp);
+    let position = q * (values.len() - 1) as f64;
+    let below = position.floor() as usize;
+    let above = position.ceil() as usize;
+    values[below] + (values[above] - values[below]) * (position - below as f64)
 }
 
 #[derive(Tsify, Debug, Clone, Serialize, Deserialize)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct ModelOutputExport {
     output: HashMap<MitigationType, HashMap<OutputType, Vec<OutputItemGrouped>>>,
+    bands: HashMap<MitigationType, HashMap<OutputType, Vec<OutputBandGrouped>>>,
+    total_bands: HashMap<MitigationType, HashMap<OutputType, Vec<OutputBandSingle>>>,
     p_detect: HashMap<MitigationType, Vec<OutputItemSingle>>,
+    vaccine_stockpile: HashMap<MitigationType, Vec<OutputItemSingle>>,
+    community_mitigations: HashMap<MitigationType, Vec<CommunityMitigationWindow>>,
+    rt: HashMap<MitigationType, Vec<OutputItemSingle>>,
     mitigation_types: Vec<MitigationType>,
     output_types: Vec<OutputType>,
 }
@@ -96,11 +337,21 @@ pub struct ModelOutputExport {
 impl ModelOutputExport {
     fn new(runs: Vec<(MitigationType, ModelOutput)>) -> Self {
         let mut output = HashMap::new();
+        let mut bands = HashMap::new();
+        let mut total_bands = HashMap::new();
         let mut p_detect = HashMap::new();
+        let mut vaccine_stockpile = HashMap::new();
+        let mut community_mitigations = HashMap::new();
+        let mut rt = HashMap::new();
         let mut mitigation_types = Vec::new();
         let output_types = OutputType::iter().collect();
         runs.iter().for_each(|(mitigation_type, o)| {
             p_detect.insert(mitigation_type.clone(), o.p_detect.clone());
+            vaccine_stockpile.insert(mitigation_type.clone(), o.vaccine_stockpile.clone());
+            community_mitigations.insert(mitigation_type.clone(), o.community_mitigations.clone());
+            rt.insert(mitigation_type.clone(), o.rt.clone());
+            bands.insert(mitigation_type.clone(), o.bands.clone());
+            total_bands.insert(mitigation_type.clone(), o.total_bands.clone());
         });
         runs.iter().for_each(|(mitigation_type, o)| {
             let mut output_map = HashMap::new();
@@ -114,7 +365,12 @@ impl ModelOutputExport {
         });
         Self {
             output,
+            bands,
+            total_bands,
             p_detect,
+            vaccine_stockpile,
+            community_mitigations,
+            rt,
             mitigation_types,
             output_types,
         }
@@ -123,16 +379,25 @@ impl ModelOutputExport {
 
 pub trait DynodeModel: Any {
     fn integrate(&self, days: usize) -> ModelOutput;
+    fn reproduction_numbers(&self) -> ReproductionNumbers;
+    fn introductions(&self) -> Introductions;
 }
 
-fn select_model(parameters: Parameters<2>) -> Box<dyn DynodeModel> {
-    // TODO maybe we'll have other models to choose from
-    Box::new(SEIRModel::new(parameters))
+// The number of groups comes with the parameters, so the models are
+// dynamically sized. Runs are deterministic unless stochastic settings are
+// given.
+fn select_model(parameters: ParametersExport) -> Result<Box<dyn DynodeModel>, &'static str> {
+    let parameters = DParameters::try_from(parameters)?;
+    if parameters.stochastic.is_some() {
+        Ok(Box::new(StochasticSEIRModelDyn::new(parameters)))
+    } else {
+        Ok(Box::new(SEIRModelDyn::new(parameters)))
+    }
 }
 
 #[wasm_bindgen]
 pub struct SEIRModelUnified {
-    parameters: Parameters<2>,
+    parameters: ParametersExport,
 }
 
 #[wasm_bindgen]
@@ -141,35 +406,52 @@ impl SEIRModelUnified {
     pub fn new(js_params: JsValue) -> Self {
         let parameters: ParametersExport =
             from_value(js_params).expect("Failed to parse parameters");
-        SEIRModelUnified {
-            parameters: parameters.try_into().unwrap(),
-        }
+        // Validate up front so that bad parameters fail at construction
+        select_model(parameters.clone()).unwrap();
+        SEIRModelUnified { parameters }
     }
 
     #[wasm_bindgen]
     pub fn run(&self, days: usize) -> ModelOutputExport {
-        let base_label: MitigationType;
         let mut runs: Vec<(MitigationType, ModelOutput)> = Vec::new();
 
         // Run an unmitigated version if necessary
-        if self.parameters.has_mitigations() {
+        let base_label = if self.parameters.has_mitigations() {
             runs.push((
                 MitigationType::Unmitigated,
-                select_model(self.parameters.without_mitigations()).integrate(days),
+                select_model(self.parameters.without_mitigations())
+                    .unwrap()
+                    .integrate(days),
             ));
-            base_label = MitigationType::Mitigated;
+            MitigationType::Mitigated
         } else {
-            base_label = MitigationType::Unmitigated;
-        }
+            MitigationType::Unmitigated
+        };
 
         // Run the base version
         runs.push((
             base_label,
-            select_model(self.parameters.clone()).integrate(days),
+            select_model(self.parameters.clone())
+                .unwrap()
+                .integrate(days),
         ));
 
         ModelOutputExport::new(runs)
     }
+
+    #[wasm_bindgen]
+    pub fn reproduction_numbers(&self) -> ReproductionNumbers {
+        select_model(self.parameters.clone())
+            .unwrap()
+            .reproduction_numbers()
+    }
+
+    #[wasm_bindgen]
+    pub fn introductions(&self) -> Introductions {
+        select_model(self.parameters.clone())
+            .unwrap()
+            .introductions()
+    }
 }
 
 #[cfg(test)]
@@ -177,20 +459,122 @@ mod tests {
     use std::any::TypeId;
 
     use super::*;
+    use crate::{MitigationParams, Parameters, StochasticParams};
 
     #[test]
     fn test_select_model() {
         let mut parameters = Parameters::default();
         parameters.mitigations.vaccine.enabled = true;
-        let model = select_model(parameters.clone());
-        assert_eq!(model.as_ref().type_id(), TypeId::of::<SEIRModel<2>>());
+        let model = select_model(parameters.clone().into()).unwrap();
+        assert_eq!(model.as_ref().type_id(), TypeId::of::<SEIRModelDyn>());
+
+        parameters.stochastic = Some(StochasticParams { seed: 1, runs: 10 });
+        let model = select_model(parameters.clone().into()).unwrap();
+        assert_eq!(
+            model.as_ref().type_id(),
+            TypeId::of::<StochasticSEIRModelDyn>()
+        );
+        parameters.stochastic = Some(StochasticParams { seed: 1, runs: 0 });
+        assert!(select_model(parameters.into()).is_err());
+    }
+
+    #[test]
+    fn test_select_model_group_count() {
+        let five_groups = ParametersExport {
+            n: 5,
+            population_fraction_labels: ["0-4", "5-17", "18-49", "50-64", "65+"]
+                .iter()
+                .map(|label| label.to_string())
+                .collect(),
+            population_fractions: vec![0.06, 0.17, 0.42, 0.19, 0.16],
+            contact_matrix: vec![1.0; 25],
+            relative_susceptibility: vec![1.0; 5],
+            relative_infectiousness: vec![1.0; 5],
+            initial_immunity: vec![0.0; 5],
+            initial_vaccinated: vec![0.0; 5],
+            fraction_symptomatic: vec![0.5; 5],
+            fraction_hospitalized: vec![0.01; 5],
+            fraction_dead: vec![0.001; 5],
+            hospital_length_of_stay: vec![5.0; 5],
+            fraction_icu: vec![0.2; 5],
+            icu_length_of_stay: vec![10.0; 5],
+            icu_capacity: None,
+            fraction_dead_icu_overflow: vec![0.0; 5],
+            mitigations: MitigationParams::<5>::default().into(),
+            ..ParametersExport::default()
+        };
+        assert!(select_model(five_groups).is_ok());
+
+        let n = 17;
+        let mut mitigations = ParametersExport::default().mitigations;
+        mitigations.community.periods[0].effectiveness = vec![0.25; n * n];
+        let many_groups = ParametersExport {
+            n,
+            population_fraction_labels: (0..n).map(|g| g.to_string()).collect(),
+            population_fractions: vec![1.0 / n as f64; n],
+            contact_matrix: vec![1.0; n * n],
+            relative_susceptibility: vec![1.0; n],
+            relative_infectiousness: vec![1.0; n],
+            initial_immunity: vec![0.0; n],
+            initial_vaccinated: vec![0.0; n],
+            fraction_symptomatic: vec![0.5; n],
+            fraction_hospitalized: vec![0.01; n],
+            fraction_dead: vec![0.001; n],
+            hospital_length_of_stay: vec![5.0; n],
+            fraction_icu: vec![0.2; n],
+            icu_length_of_stay: vec![10.0; n],
+            icu_capacity: None,
+            fraction_dead_icu_overflow: vec![0.0; n],
+            mitigations,
+            ..ParametersExport::default()
+        };
+        assert!(select_model(many_groups).is_ok());
+
+        let invalid = ParametersExport {
+            n: 3,
+            ..ParametersExport::default()
+        };
+        assert!(select_model(invalid).is_err());
+    }
+
+    #[test]
+    fn test_run_group_count() {
+        let n = 16;
+        let parameters = ParametersExport {
+            n,
+            population_fraction_labels: (0..n)
+                .map(|g| format!("{}-{}", 5 * g, 5 * g + 4))
+                .collect(),
+            population_fractions: vec![1.0 / n as f64; n],
+            contact_matrix: vec![1.0; n * n],
+            relative_susceptibility: vec![1.0; n],
+            relative_infectiousness: vec![1.0; n],
+            initial_immunity: vec![0.0; n],
+            initial_vaccinated: vec![0.0; n],
+            fraction_symptomatic: vec![0.5; n],
+            fraction_hospitalized: vec![0.01; n],
+            fraction_dead: vec![0.001; n],
+            hospital_length_of_stay: vec![5.0; n],
+            fraction_icu: vec![0.2; n],
+            icu_length_of_stay: vec![10.0; n],
+            icu_capacity: None,
+            fraction_dead_icu_overflow: vec![0.0; n],
+            mitigations: MitigationParams::<16>::default().into(),
+            ..ParametersExport::default()
+        };
+        let model = SEIRModelUnified { parameters };
+        let run = model.run(200);
+        let incidence = &run.output[&MitigationType::Unmitigated][&OutputType::InfectionIncidence];
+        assert!(incidence.iter().all(|item| item.grouped_values.len() == n));
     }
 
     #[test]
     fn test_without_mitigations() {
         let mut parameters = Parameters::default();
         parameters.mitigations.vaccine.enabled = false;
-        let model = SEIRModelUnified { parameters };
+        let model = SEIRModelUnified {
+            parameters: parameters.into(),
+        };
         let run = model.run(200);
         assert!(!run.output.contains_key(&MitigationType::Mitigated));
         assert!(run.output.contains_key(&MitigationType::Unmitigated));
@@ -201,10 +585,60 @@ mod tests {
     fn test_with_mitigations() {
         let mut parameters = Parameters::default();
         parameters.mitigations.vaccine.enabled = true;
-        let model = SEIRModelUnified { parameters };
+        let model = SEIRModelUnified {
+            parameters: parameters.into(),
+        };
         let run = model.run(200);
         assert!(run.output.contains_key(&MitigationType::Mitigated));
         assert!(run.output.contains_key(&MitigationType::Unmitigated));
         assert_eq!(run.mitigation_types.len(), 2);
     }
+
+    #[test]
+    fn test_stochastic_run() {
+        let mut parameters = Parameters {
+            population: 1_000_000.0,
+            stochastic: Some(StochasticParams { seed: 1, runs: 3 }),
+            ..Default::default()
+        };
+        parameters.mitigations.vaccine.enabled = true;
+        let model = SEIRModelUnified {
+            parameters: parameters.into(),
+        };
+        let run = model.run(100);
+        for mitigation_type in &run.mitigation_types {
+            let bands = &run.bands[mitigation_type][&OutputType::InfectionIncidence];
+            assert_eq!(bands.len(), 100);
+            let total_bands = &run.total_bands[mitigation_type][&OutputType::InfectionIncidence];
+            assert_eq!(total_bands.len(), 100);
+        }
+
+        // Deterministic runs have no bands
+        let model = SEIRModelUnified {
+            parameters: Parameters::default().into(),
+        };
+        let run = model.run(100);
+        assert!(
+            run.bands[&MitigationType::Unmitigated][&OutputType::InfectionIncidence].is_empty()
+        );
+    }
+
+    #[test]
+    fn test_ensemble_total_band() {
+        // The groups vary in opposite directions between runs, so that the
+        // total does not vary at all
+        let ensemble: Vec<_> = [[0.0, 2.0], [1.0, 1.0], [2.0, 0.0]]
+            .iter()
+            .map(|values| {
+                let mut output = ModelOutput::new();
+                output.add_infection_incidence(1.0, values.to_vec());
+                output
+            })
+            .collect();
+        let output = ModelOutput::from_ensemble(&ensemble, 0.0, 1.0);
+        let band = &output.get_band(&OutputType::InfectionIncidence)[0];
+        assert_eq!((&band.lower, &band.upper), (&vec![0.0; 2], &vec![2.0; 2]));
+        let total_band = &output.get_total_band(&OutputType::InfectionIncidence)[0];
+        assert_eq!((total_band.lower, total_band.upper), (2.0, 2.0));
+    }
 }
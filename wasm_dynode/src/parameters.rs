@@ -1,26 +1,179 @@
-use nalgebra::{SMatrix, SVector, matrix, vector};
+use nalgebra::{
+    Const, DefaultAllocator, Dim, Dyn, OMatrix, OVector, U1, allocator::Allocator, matrix, vector,
+};
 use serde::{Deserialize, Serialize};
 use tsify::Tsify;
 use wasm_bindgen::prelude::*;
 
-use crate::{MitigationParams, MitigationParamsExport};
+use crate::{GenericMitigationParams, MitigationParams, MitigationParamsExport};
 
+// Bound on the number of times the bracket of the growth rate is doubled,
+// which is only reached for a huge R0
+const MAX_GROWTH_RATE_DOUBLINGS: usize = 64;
+// Number of times the bracket of the growth rate is halved, enough to narrow
+// down any bracket to the precision of an f64
+const GROWTH_RATE_BISECTIONS: usize = 200;
+
+/// Setting in which contacts happen
+#[derive(Tsify, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum ContactSetting {
+    Home,
+    School,
+    Work,
+    Community,
+}
+
+/// Contacts between groups in one setting
+#[derive(Debug, Clone)]
+pub struct GenericSettingContacts<D: Dim>
+where
+    DefaultAllocator: Allocator<D, D>,
+{
+    pub setting: ContactSetting,
+    pub contact_matrix: OMatrix<f64, D, D>,
+}
+
+/// Contacts in one setting, for a compile-time number of groups `N`
+pub type SettingContacts<const N: usize> = GenericSettingContacts<Const<N>>;
+
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SettingContactsExport {
+    pub setting: ContactSetting,
+    pub contact_matrix: Vec<f64>,
+}
+
+/// Multiplier of R on a given day, interpolated linearly between days
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct RMultiplierPoint {
+    pub day: f64,
+    pub multiplier: f64,
+}
+
+/// Infections imported per day from a given day on, until the next change.
+/// Imported infections are spread over the groups by population, or by the
+/// given shares, and the rate is reduced by the fraction of each group no
+/// longer susceptible.
+#[derive(Tsify, Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ImportationPeriod {
+    pub start: f64,
+    pub rate: f64,
+    pub group_shares: Option<Vec<f64>>,
+}
+
+/// Second strain of the pathogen, introduced during the epidemic, which
+/// competes with the first
+#[derive(Tsify, Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct VariantParams {
+    // transmissibility relative to the first strain, so that the R0 of the
+    // variant is `relative_transmissibility * r0`
+    pub relative_transmissibility: f64,
+    // day on which infections with the variant are introduced, spread over
+    // the groups by population, into the latent period
+    pub introduction_day: usize,
+    pub introduced_infections: f64,
+    // fraction of the vaccine efficacies (VE_S, VE_I and VE_P) lost against
+    // the variant
+    pub vaccine_escape: f64,
+    // fraction of the protection from past infection with the first strain
+    // lost against the variant
+    pub infection_escape: f64,
+}
+
+impl VariantParams {
+    /// Efficacy (VE_S, VE_I, VE_P) against the variant of a vaccine with
+    /// efficacy `efficacy` against the first strain
+    pub fn efficacy(&self, (ve_s, ve_i, ve_p): (f64, f64, f64)) -> (f64, f64, f64) {
+        let kept = 1.0 - self.vaccine_escape;
+        (ve_s * kept, ve_i * kept, ve_p * kept)
+    }
+}
+
+/// Settings of stochastic runs of the model
+#[derive(Tsify, Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct StochasticParams {
+    // seed of the random number generator, so that runs can be reproduced
+    pub seed: u32,
+    // number of runs in the ensemble
+    pub runs: usize,
+}
+
+/// Model parameters, generic over the dimension `D` of the population groups.
+///
+/// Use [`Parameters<N>`] when the number of groups is known at compile time
+/// and [`DParameters`] when it comes from user data.
 #[derive(Debug, Clone)]
-pub struct Parameters<const N: usize> {
+pub struct GenericParameters<D: Dim>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
     pub population: f64,
-    pub population_fractions: SVector<f64, N>,
-    pub population_fraction_labels: SVector<String, N>,
-    pub contact_matrix: SMatrix<f64, N, N>,
+    pub population_fractions: OVector<f64, D>,
+    pub population_fraction_labels: OVector<String, D>,
+    pub contact_matrix: OMatrix<f64, D, D>,
+    // Contacts in named settings, which add up with `contact_matrix` to the
+    // total number of contacts
+    pub setting_contacts: Vec<GenericSettingContacts<D>>,
+    // relative probability of infection given a contact with an infectious person
+    pub relative_susceptibility: OVector<f64, D>,
+    // relative probability of infecting a susceptible contact
+    pub relative_infectiousness: OVector<f64, D>,
     pub initial_infections: f64,
+    // Share of the initial infections in each group, in proportion to the
+    // group sizes if `None`
+    pub initial_infection_shares: Option<OVector<f64, D>>,
+    // fraction of each group initially immune from a past infection (R)
+    pub initial_immunity: OVector<f64, D>,
+    // fraction of each group initially vaccinated with the complete
+    // schedule (SV), apart from those initially immune
+    pub initial_vaccinated: OVector<f64, D>,
+    // Infections of susceptible people imported over time, empty for no
+    // importations
+    pub importations: Vec<ImportationPeriod>,
     pub r0: f64,
+    // Seasonal forcing of transmission: relative amplitude of the annual
+    // sinusoid, and day of the simulation on which transmission peaks
+    pub seasonal_amplitude: f64,
+    pub seasonal_peak_day: f64,
+    // Piecewise-linear multiplier of R over time, empty for no change
+    pub r_multiplier: Vec<RMultiplierPoint>,
     pub latent_period: f64,
+    // number of stages of the Erlang-distributed latent period
+    pub latent_stages: usize,
     pub infectious_period: f64,
-    pub fraction_symptomatic: SVector<f64, N>,
-    pub fraction_hospitalized: SVector<f64, N>,
+    // number of stages of the Erlang-distributed infectious period
+    pub infectious_stages: usize,
+    // Waning immunity rates (per day), zero for lifelong immunity
+    // after infection, unvaccinated (R -> S)
+    pub recovered_waning_rate: f64,
+    // after infection, vaccinated (RV -> SV)
+    pub recovered_vaccinated_waning_rate: f64,
+    // vaccine protection (SV -> S)
+    pub vaccine_waning_rate: f64,
+    pub fraction_symptomatic: OVector<f64, D>,
+    // infectiousness of asymptomatic relative to symptomatic infections
+    pub asymptomatic_relative_infectiousness: f64,
+    pub fraction_hospitalized: OVector<f64, D>,
     pub hospitalization_delay: f64,
-    pub fraction_dead: SVector<f64, N>,
+    // mean length of stay (days) in hospital, outside of intensive care
+    pub hospital_length_of_stay: OVector<f64, D>,
+    // fraction of hospitalizations that need intensive care
+    pub fraction_icu: OVector<f64, D>,
+    // mean length of stay (days) in intensive care
+    pub icu_length_of_stay: OVector<f64, D>,
+    // number of intensive care beds, unlimited if `None`
+    pub icu_capacity: Option<f64>,
+    // additional fraction of intensive care admissions who die when they are
+    // turned away because all intensive care beds are occupied
+    pub fraction_dead_icu_overflow: OVector<f64, D>,
+    pub fraction_dead: OVector<f64, D>,
     pub death_delay: f64,
-    pub mitigations: MitigationParams<N>,
+    pub mitigations: GenericMitigationParams<D>,
     // Detection parameters
     // prob. of test given symptomatic infection
     pub p_test_sympto: f64,
@@ -28,9 +181,139 @@ pub struct Parameters<const N: usize> {
     pub test_sensitivity: f64,
     // prob. of forwarding a positive test
     pub p_test_forward: f64,
+    // second strain, if any
+    pub variant: Option<VariantParams>,
+    // run as a stochastic process rather than the ODE, if set
+    pub stochastic: Option<StochasticParams>,
 }
 
-impl<const N: usize> Parameters<N> {
+/// Parameters for a compile-time number of groups `N`
+pub type Parameters<const N: usize> = GenericParameters<Const<N>>;
+
+/// Parameters for a number of groups only known at runtime
+pub type DParameters = GenericParameters<Dyn>;
+
+impl<D: Dim> GenericParameters<D>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    /// Number of population groups
+    pub fn n(&self) -> usize {
+        self.population_fractions.len()
+    }
+    /// Contacts between groups in all settings
+    pub fn total_contact_matrix(&self) -> OMatrix<f64, D, D> {
+        self.setting_contacts
+            .iter()
+            .fold(self.contact_matrix.clone(), |total, setting| {
+                total + &setting.contact_matrix
+            })
+    }
+    /// Mean infectiousness of an infection in each group, relative to a
+    /// symptomatic infection
+    pub fn mean_infectiousness(&self) -> OVector<f64, D> {
+        self.fraction_symptomatic
+            .map(|fs| fs + (1.0 - fs) * self.asymptomatic_relative_infectiousness)
+    }
+    /// Number of initial infections in each group
+    pub fn initial_infections_by_group(&self) -> OVector<f64, D> {
+        let shares = self
+            .initial_infection_shares
+            .as_ref()
+            .unwrap_or(&self.population_fractions);
+        shares / shares.sum() * self.initial_infections
+    }
+    /// Infections imported per day in each group on day `t`
+    pub fn importations_at(&self, t: f64) -> OVector<f64, D> {
+        let (d, _) = self.population_fractions.shape_generic();
+        match self
+            .importations
+            .iter()
+            .rev()
+            .find(|period| period.start <= t)
+        {
+            None => OVector::zeros_generic(d, U1),
+            Some(period) => {
+                let shares = match &period.group_shares {
+                    Some(shares) => OVector::from_iterator_generic(d, U1, shares.iter().copied()),
+                    None => self.population_fractions.clone(),
+                };
+                &shares / shares.sum() * period.rate
+            }
+        }
+    }
+    /// Relative change in transmission on day `t`, from seasonal forcing and
+    /// the R multiplier
+    pub fn transmission_multiplier(&self, t: f64) -> f64 {
+        let seasonal = 1.0
+            + self.seasonal_amplitude
+                * (2.0 * std::f64::consts::PI * (t - self.seasonal_peak_day) / 365.0).cos();
+        seasonal * r_multiplier_at(&self.r_multiplier, t)
+    }
+    /// R0 for an initial growth rate of infections (per day), given the
+    /// latent and infectious periods and the transmission multiplier on the
+    /// first day. The contact matrix is normalized so that it only matters
+    /// through R0. Infections cannot decline faster than the latent or
+    /// infectious stages are left.
+    pub fn r0_from_growth_rate(&self, growth_rate: f64) -> Result<f64, &'static str> {
+        if !growth_rate.is_finite() || growth_rate <= self.min_growth_rate() {
+            return Err("Invalid growth rate");
+        }
+        Ok(self.generation_r(growth_rate) / self.transmission_multiplier(0.0))
+    }
+    /// R0 for an initial doubling time of infections (days)
+    pub fn r0_from_doubling_time(&self, doubling_time: f64) -> Result<f64, &'static str> {
+        if !doubling_time.is_finite() || doubling_time <= 0.0 {
+            return Err("Invalid doubling time");
+        }
+        self.r0_from_growth_rate(std::f64::consts::LN_2 / doubling_time)
+    }
+    /// Initial growth rate of infections (per day) for R0, negative when the
+    /// epidemic declines
+    pub fn growth_rate(&self) -> f64 {
+        let target = self.r0 * self.transmission_multiplier(0.0);
+        // R increases with the growth rate, from zero at the lowest growth
+        // rate
+        let mut lower = self.min_growth_rate();
+        let mut upper = 1.0_f64;
+        for _ in 0..MAX_GROWTH_RATE_DOUBLINGS {
+            if self.generation_r(upper) >= target {
+                break;
+            }
+            upper *= 2.0;
+        }
+        for _ in 0..GROWTH_RATE_BISECTIONS {
+            let middle = 0.5 * (lower + upper);
+            if self.generation_r(middle) < target {
+                lower = middle;
+            } else {
+                upper = middle;
+            }
+        }
+        0.5 * (lower + upper)
+    }
+    // Growth rate (per day) at which R reaches zero, when the latent or
+    // infectious stages are left faster than infections decline
+    fn min_growth_rate(&self) -> f64 {
+        -(self.latent_stages as f64 / self.latent_period)
+            .min(self.infectious_stages as f64 / self.infectious_period)
+    }
+    // Reproduction number for a growth rate `r`, from the Laplace transform
+    // of the generation interval distribution, with Erlang distributed
+    // latent and infectious periods:
+    // R = r T_I (1 + r T_E / k_E)^k_E / (1 - (1 + r T_I / k_I)^-k_I)
+    // which only holds for growth rates above `min_growth_rate`
+    fn generation_r(&self, r: f64) -> f64 {
+        if r.abs() < 1e-12 {
+            return 1.0;
+        }
+        let latent = (1.0 + r * self.latent_period / self.latent_stages as f64)
+            .powi(self.latent_stages as i32);
+        let infectious = 1.0
+            - (1.0 + r * self.infectious_period / self.infectious_stages as f64)
+                .powi(-(self.infectious_stages as i32));
+        r * self.infectious_period * latent / infectious
+    }
     pub fn has_mitigations(&self) -> bool {
         self.mitigations.antivirals.enabled
             || self.mitigations.community.enabled
@@ -45,6 +328,19 @@ impl<const N: usize> Parameters<N> {
     }
 }
 
+/// Linear interpolation of the R multiplier, constant before the first and
+/// after the last day
+fn r_multiplier_at(points: &[RMultiplierPoint], t: f64) -> f64 {
+    match points.iter().position(|point| point.day > t) {
+        None => points.last().map_or(1.0, |point| point.multiplier),
+        Some(0) => points[0].multiplier,
+        Some(next) => {
+            let (a, b) = (&points[next - 1], &points[next]);
+            a.multiplier + (b.multiplier - a.multiplier) * (t - a.day) / (b.day - a.day)
+        }
+    }
+}
+
 impl Default for Parameters<2> {
     fn default() -> Self {
         Parameters {
@@ -53,24 +349,47 @@ impl Default for Parameters<2> {
             population_fraction_labels: vector!["Children".to_string(), "Adults".to_string()],
             contact_matrix: matrix![18.0, 3.0;
                                     9.0, 12.0],
+            setting_contacts: Vec::new(),
+            relative_susceptibility: vector![1.0, 1.0],
+            relative_infectiousness: vector![1.0, 1.0],
             initial_infections: 1_000.0,
+            initial_infection_shares: None,
+            initial_immunity: vector![0.0, 0.0],
+            initial_vaccinated: vector![0.0, 0.0],
+            importations: Vec::new(),
             r0: 1.5,
+            seasonal_amplitude: 0.0,
+            seasonal_peak_day: 0.0,
+            r_multiplier: Vec::new(),
             latent_period: 1.0,
+            latent_stages: 1,
             infectious_period: 2.5,
+            infectious_stages: 1,
+            recovered_waning_rate: 0.0,
+            recovered_vaccinated_waning_rate: 0.0,
+            vaccine_waning_rate: 0.0,
             fraction_symptomatic: vector![0.5, 0.5],
+            asymptomatic_relative_infectiousness: 1.0,
             fraction_hospitalized: vector![0.01, 0.1],
             hospitalization_delay: 7.0,
+            hospital_length_of_stay: vector![4.0, 6.0],
+            fraction_icu: vector![0.1, 0.2],
+            icu_length_of_stay: vector![7.0, 10.0],
+            icu_capacity: None,
+            fraction_dead_icu_overflow: vector![0.2, 0.5],
             fraction_dead: vector![0.0005, 0.005],
             death_delay: 10.0,
             mitigations: {
                 let mut default = MitigationParams::default();
-                default.community.effectiveness = matrix![0.5, -0.10;
+                default.community.periods[0].effectiveness = matrix![0.5, -0.10;
                                                                -0.10, 0.0];
                 default
             },
             p_test_sympto: 0.0,
             test_sensitivity: 0.90,
             p_test_forward: 0.90,
+            variant: None,
+            stochastic: None,
         }
     }
 }
@@ -83,77 +402,374 @@ pub struct ParametersExport {
     pub population_fraction_labels: Vec<String>,
     pub population_fractions: Vec<f64>,
     pub contact_matrix: Vec<f64>,
+    pub setting_contacts: Vec<SettingContactsExport>,
+    pub relative_susceptibility: Vec<f64>,
+    pub relative_infectiousness: Vec<f64>,
     pub initial_infections: f64,
+    pub initial_infection_shares: Option<Vec<f64>>,
+    pub initial_immunity: Vec<f64>,
+    pub initial_vaccinated: Vec<f64>,
+    pub importations: Vec<ImportationPeriod>,
     pub r0: f64,
+    pub seasonal_amplitude: f64,
+    pub seasonal_peak_day: f64,
+    pub r_multiplier: Vec<RMultiplierPoint>,
     pub latent_period: f64,
+    pub latent_stages: usize,
     pub infectious_period: f64,
+    pub infectious_stages: usize,
+    pub recovered_waning_rate: f64,
+    pub recovered_vaccinated_waning_rate: f64,
+    pub vaccine_waning_rate: f64,
     pub fraction_symptomatic: Vec<f64>,
+    pub asymptomatic_relative_infectiousness: f64,
     pub fraction_hospitalized: Vec<f64>,
     pub hospitalization_delay: f64,
+    pub hospital_length_of_stay: Vec<f64>,
+    pub fraction_icu: Vec<f64>,
+    pub icu_length_of_stay: Vec<f64>,
+    pub icu_capacity: Option<f64>,
+    pub fraction_dead_icu_overflow: Vec<f64>,
     pub fraction_dead: Vec<f64>,
     pub death_delay: f64,
     pub mitigations: MitigationParamsExport,
     pub p_test_sympto: f64,
     pub test_sensitivity: f64,
     pub p_test_forward: f64,
+    pub variant: Option<VariantParams>,
+    pub stochastic: Option<StochasticParams>,
+}
+
+impl ParametersExport {
+    pub fn has_mitigations(&self) -> bool {
+        self.mitigations.antivirals.enabled
+            || self.mitigations.community.enabled
+            || self.mitigations.vaccine.enabled
+    }
+    pub fn without_mitigations(&self) -> Self {
+        let mut params = self.clone();
+        params.mitigations.antivirals.enabled = false;
+        params.mitigations.community.enabled = false;
+        params.mitigations.vaccine.enabled = false;
+        params
+    }
 }
 
-impl<const N: usize> TryFrom<ParametersExport> for Parameters<N> {
+impl<D: Dim> TryFrom<ParametersExport> for GenericParameters<D>
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
     type Error = &'static str;
     fn try_from(params: ParametersExport) -> Result<Self, Self::Error> {
+        let n = params.n;
+
         // Validate
-        if params.population_fractions.len() != N {
+        if D::try_to_usize().is_some_and(|size| size != n) {
+            return Err("Invalid number of population groups");
+        }
+        if params.population_fractions.len() != n {
             return Err("Invalid number of population fractions");
         }
-        if params.contact_matrix.len() != N * N {
+        if params.population_fraction_labels.len() != n {
+            return Err("Invalid number of population fraction labels");
+        }
+        if params.contact_matrix.len() != n * n
+            || params
+                .setting_contacts
+                .iter()
+                .any(|setting| setting.contact_matrix.len() != n * n)
+        {
             return Err("Invalid number of contact matrix elements");
         }
+        if params
+            .setting_contacts
+            .iter()
+            .enumerate()
+            .any(|(k, setting)| {
+                params.setting_contacts[..k]
+                    .iter()
+                    .any(|other| other.setting == setting.setting)
+            })
+        {
+            return Err("Duplicate contact setting");
+        }
+        if params.relative_susceptibility.len() != n || params.relative_infectiousness.len() != n {
+            return Err("Invalid number of relative susceptibilities or infectiousnesses");
+        }
+        if params
+            .relative_susceptibility
+            .iter()
+            .chain(params.relative_infectiousness.iter())
+            .any(|&x| x < 0.0)
+        {
+            return Err("Invalid relative susceptibility or infectiousness");
+        }
+        if params.fraction_symptomatic.len() != n
+            || params.fraction_hospitalized.len() != n
+            || params.fraction_dead.len() != n
+        {
+            return Err("Invalid number of group outcome fractions");
+        }
+        if params.hospital_length_of_stay.len() != n
+            || params.fraction_icu.len() != n
+            || params.icu_length_of_stay.len() != n
+            || params.fraction_dead_icu_overflow.len() != n
+        {
+            return Err("Invalid number of group hospital parameters");
+        }
+        if params
+            .fraction_icu
+            .iter()
+            .chain(params.fraction_dead_icu_overflow.iter())
+            .any(|fraction| !(0.0..=1.0).contains(fraction))
+        {
+            return Err("Invalid intensive care fractions");
+        }
+        if params
+            .hospital_length_of_stay
+            .iter()
+            .chain(params.icu_length_of_stay.iter())
+            .any(|&los| los <= 0.0)
+        {
+            return Err("Invalid hospital length of stay");
+        }
+        if !(0.0..=1.0).contains(&params.asymptomatic_relative_infectiousness) {
+            return Err("Invalid asymptomatic relative infectiousness");
+        }
+        if params.icu_capacity.is_some_and(|capacity| capacity < 0.0) {
+            return Err("Invalid intensive care capacity");
+        }
+        if !params.r0.is_finite() || params.r0 < 0.0 {
+            return Err("Invalid R0");
+        }
+        if !(0.0..=1.0).contains(&params.seasonal_amplitude) {
+            return Err("Invalid seasonal amplitude");
+        }
+        if params
+            .r_multiplier
+            .iter()
+            .any(|point| point.multiplier < 0.0)
+            || params.r_multiplier.windows(2).any(|w| w[0].day >= w[1].day)
+        {
+            return Err("Invalid R multiplier");
+        }
+        if params.importations.iter().any(|period| {
+            period.rate < 0.0
+                || period.group_shares.as_ref().is_some_and(|shares| {
+                    shares.len() != n
+                        || shares.iter().any(|&share| share < 0.0)
+                        || shares.iter().sum::<f64>() <= 0.0
+                })
+        }) || params
+            .importations
+            .windows(2)
+            .any(|w| w[0].start >= w[1].start)
+        {
+            return Err("Invalid importations");
+        }
+        let infection_shares = params
+            .initial_infection_shares
+            .as_ref()
+            .unwrap_or(&params.population_fractions);
+        if infection_shares.len() != n
+            || infection_shares.iter().any(|&share| share < 0.0)
+            || infection_shares.iter().sum::<f64>() <= 0.0
+        {
+            return Err("Invalid initial infection shares");
+        }
+        if params.initial_immunity.len() != n
+            || params.initial_vaccinated.len() != n
+            || params
+                .initial_immunity
+                .iter()
+                .zip(&params.initial_vaccinated)
+                .any(|(&immune, &vaccinated)| {
+                    immune < 0.0 || vaccinated < 0.0 || immune + vaccinated > 1.0
+                })
+        {
+            return Err("Invalid initial immunity or vaccination");
+        }
+        // Initial infections come from those neither immune nor vaccinated
+        let total_shares: f64 = infection_shares.iter().sum();
+        if (0..n).any(|group| {
+            params.initial_infections * infection_shares[group] / total_shares
+                > params.population
+                    * params.population_fractions[group]
+                    * (1.0 - params.initial_immunity[group] - params.initial_vaccinated[group])
+        }) {
+            return Err("Too many initial infections");
+        }
+        if params.latent_stages == 0 || params.infectious_stages == 0 {
+            return Err("Invalid number of latent or infectious stages");
+        }
+        if [
+            params.recovered_waning_rate,
+            params.recovered_vaccinated_waning_rate,
+            params.vaccine_waning_rate,
+        ]
+        .iter()
+        .any(|rate| !rate.is_finite() || *rate < 0.0)
+        {
+            return Err("Invalid waning rate");
+        }
+        if params.variant.is_some_and(|variant| {
+            variant.relative_transmissibility < 0.0
+                || variant.introduced_infections < 0.0
+                || !(0.0..=1.0).contains(&variant.vaccine_escape)
+                || !(0.0..=1.0).contains(&variant.infection_escape)
+        }) {
+            return Err("Invalid variant");
+        }
+        if params
+            .stochastic
+            .is_some_and(|stochastic| stochastic.runs == 0)
+        {
+            return Err("Invalid number of stochastic runs");
+        }
 
-        Ok(Parameters {
+        let d = D::from_usize(n);
+        Ok(GenericParameters {
             population: params.population,
-            population_fractions: SVector::from_iterator(params.population_fractions.into_iter()),
-            population_fraction_labels: SVector::from_iterator(
-                params.population_fraction_labels.into_iter(),
+            population_fractions: OVector::from_iterator_generic(
+                d,
+                U1,
+                params.population_fractions,
+            ),
+            population_fraction_labels: OVector::from_iterator_generic(
+                d,
+                U1,
+                params.population_fraction_labels,
+            ),
+            contact_matrix: OMatrix::from_iterator_generic(d, d, params.contact_matrix),
+            setting_contacts: params
+                .setting_contacts
+                .into_iter()
+                .map(|setting| GenericSettingContacts {
+                    setting: setting.setting,
+                    contact_matrix: OMatrix::from_iterator_generic(d, d, setting.contact_matrix),
+                })
+                .collect(),
+            relative_susceptibility: OVector::from_iterator_generic(
+                d,
+                U1,
+                params.relative_susceptibility,
+            ),
+            relative_infectiousness: OVector::from_iterator_generic(
+                d,
+                U1,
+                params.relative_infectiousness,
             ),
-            contact_matrix: SMatrix::from_iterator(params.contact_matrix.into_iter()),
             initial_infections: params.initial_infections,
+            initial_infection_shares: params
+                .initial_infection_shares
+                .map(|shares| OVector::from_iterator_generic(d, U1, shares)),
+            initial_immunity: OVector::from_iterator_generic(d, U1, params.initial_immunity),
+            initial_vaccinated: OVector::from_iterator_generic(d, U1, params.initial_vaccinated),
+            importations: params.importations,
             r0: params.r0,
+            seasonal_amplitude: params.seasonal_amplitude,
+            seasonal_peak_day: params.seasonal_peak_day,
+            r_multiplier: params.r_multiplier,
             latent_period: params.latent_period,
+            latent_stages: params.latent_stages,
             infectious_period: params.infectious_period,
-            fraction_symptomatic: SVector::from_iterator(params.fraction_symptomatic),
-            fraction_hospitalized: SVector::from_iterator(params.fraction_hospitalized),
+            infectious_stages: params.infectious_stages,
+            recovered_waning_rate: params.recovered_waning_rate,
+            recovered_vaccinated_waning_rate: params.recovered_vaccinated_waning_rate,
+            vaccine_waning_rate: params.vaccine_waning_rate,
+            fraction_symptomatic: OVector::from_iterator_generic(
+                d,
+                U1,
+                params.fraction_symptomatic,
+            ),
+            asymptomatic_relative_infectiousness: params.asymptomatic_relative_infectiousness,
+            fraction_hospitalized: OVector::from_iterator_generic(
+                d,
+                U1,
+                params.fraction_hospitalized,
+            ),
             hospitalization_delay: params.hospitalization_delay,
-            fraction_dead: SVector::from_iterator(params.fraction_dead),
+            hospital_length_of_stay: OVector::from_iterator_generic(
+                d,
+                U1,
+                params.hospital_length_of_stay,
+            ),
+            fraction_icu: OVector::from_iterator_generic(d, U1, params.fraction_icu),
+            icu_length_of_stay: OVector::from_iterator_generic(d, U1, params.icu_length_of_stay),
+            icu_capacity: params.icu_capacity,
+            fraction_dead_icu_overflow: OVector::from_iterator_generic(
+                d,
+                U1,
+                params.fraction_dead_icu_overflow,
+            ),
+            fraction_dead: OVector::from_iterator_generic(d, U1, params.fraction_dead),
             death_delay: params.death_delay,
-            mitigations: MitigationParams::try_from(params.mitigations)?,
+            mitigations: GenericMitigationParams::try_from_export(params.mitigations, d)?,
             p_test_sympto: params.p_test_sympto,
             test_sensitivity: params.test_sensitivity,
             p_test_forward: params.p_test_forward,
+            variant: params.variant,
+            stochastic: params.stochastic,
         })
     }
 }
 
-impl<const N: usize> From<Parameters<N>> for ParametersExport {
-    fn from(params: Parameters<N>) -> Self {
+impl<D: Dim> From<GenericParameters<D>> for ParametersExport
+where
+    DefaultAllocator: Allocator<D> + Allocator<D, D>,
+{
+    fn from(params: GenericParameters<D>) -> Self {
         ParametersExport {
-            n: N,
+            n: params.n(),
             population: params.population,
             population_fractions: params.population_fractions.iter().copied().collect(),
             population_fraction_labels: params.population_fraction_labels.iter().cloned().collect(),
             contact_matrix: params.contact_matrix.iter().copied().collect(),
+            setting_contacts: params
+                .setting_contacts
+                .iter()
+                .map(|setting| SettingContactsExport {
+                    setting: setting.setting,
+                    contact_matrix: setting.contact_matrix.iter().copied().collect(),
+                })
+                .collect(),
+            relative_susceptibility: params.relative_susceptibility.iter().copied().collect(),
+            relative_infectiousness: params.relative_infectiousness.iter().copied().collect(),
             initial_infections: params.initial_infections,
+            initial_infection_shares: params
+                .initial_infection_shares
+                .map(|shares| shares.iter().copied().collect()),
+            initial_immunity: params.initial_immunity.iter().copied().collect(),
+            initial_vaccinated: params.initial_vaccinated.iter().copied().collect(),
+            importations: params.importations,
             r0: params.r0,
+            seasonal_amplitude: params.seasonal_amplitude,
+            seasonal_peak_day: params.seasonal_peak_day,
+            r_multiplier: params.r_multiplier,
             latent_period: params.latent_period,
+            latent_stages: params.latent_stages,
             infectious_period: params.infectious_period,
+            infectious_stages: params.infectious_stages,
+            recovered_waning_rate: params.recovered_waning_rate,
+            recovered_vaccinated_waning_rate: params.recovered_vaccinated_waning_rate,
+            vaccine_waning_rate: params.vaccine_waning_rate,
             fraction_symptomatic: params.fraction_symptomatic.iter().copied().collect(),
+            asymptomatic_relative_infectiousness: params.asymptomatic_relative_infectiousness,
             fraction_hospitalized: params.fraction_hospitalized.iter().copied().collect(),
             hospitalization_delay: params.hospitalization_delay,
+            hospital_length_of_stay: params.hospital_length_of_stay.iter().copied().collect(),
+            fraction_icu: params.fraction_icu.iter().copied().collect(),
+            icu_length_of_stay: params.icu_length_of_stay.iter().copied().collect(),
+            icu_capacity: params.icu_capacity,
+            fraction_dead_icu_overflow: params.fraction_dead_icu_overflow.iter().copied().collect(),
             fraction_dead: params.fraction_dead.iter().copied().collect(),
-            death_delay: params.hospitalization_delay,
+            death_delay: params.death_delay,
             mitigations: params.mitigations.into(),
             p_test_sympto: params.p_test_sympto,
             test_sensitivity: params.test_sensitivity,
             p_test_forward: params.p_test_forward,
+            variant: params.variant,
+            stochastic: params.stochastic,
         }
     }
 }
@@ -169,9 +785,52 @@ pub fn get_default_parameters() -> ParametersExport {
     ParametersExport::default()
 }
 
+/// R0 for an observed initial growth rate of infections (per day)
+#[wasm_bindgen]
+pub fn r0_from_growth_rate(parameters: ParametersExport, growth_rate: f64) -> Result<f64, JsError> {
+    DParameters::try_from(parameters)
+        .map_err(JsError::new)?
+        .r0_from_growth_rate(growth_rate)
+        .map_err(JsError::new)
+}
+
+/// R0 for an observed doubling time of infections (days)
+#[wasm_bindgen]
+pub fn r0_from_doubling_time(
+    parameters: ParametersExport,
+    doubling_time: f64,
+) -> Result<f64, JsError> {
+    DParameters::try_from(parameters)
+        .map_err(JsError::new)?
+        .r0_from_doubling_time(doubling_time)
+        .map_err(JsError::new)
+}
+
+/// Initial growth rate of infections (per day) given R0
+#[wasm_bindgen]
+pub fn get_growth_rate(parameters: ParametersExport) -> Result<f64, JsError> {
+    Ok(DParameters::try_from(parameters)
+        .map_err(JsError::new)?
+        .growth_rate())
+}
+
+/// Doubling time of infections (days) given R0, infinite when the epidemic
+/// does not grow
+#[wasm_bindgen]
+pub fn get_doubling_time(parameters: ParametersExport) -> Result<f64, JsError> {
+    let growth_rate = get_growth_rate(parameters)?;
+    Ok(if growth_rate > 0.0 {
+        std::f64::consts::LN_2 / growth_rate
+    } else {
+        f64::INFINITY
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::VaccineAllocation;
+    use float_eq::assert_float_eq;
 
     #[test]
     fn test_has_mitigations() {
@@ -206,16 +865,299 @@ mod tests {
         let fractions = (vector![0.1, 0.9], vec![0.1, 0.9]);
         let matrix = (matrix![18.0, 3.0; 9.0, 12.0], vec![18.0, 9.0, 3.0, 12.0]);
 
-        let mut params = Parameters::<2>::default();
-        params.population_fractions = fractions.0;
-        params.contact_matrix = matrix.0;
+        let params = Parameters::<2> {
+            population_fractions: fractions.0,
+            contact_matrix: matrix.0,
+            ..Default::default()
+        };
 
         let export: ParametersExport = params.clone().into();
         assert_eq!(export.population_fractions, fractions.1);
         assert_eq!(export.contact_matrix, matrix.1);
 
-        let params2: Parameters<2> = export.try_into().unwrap();
+        let params2: Parameters<2> = export.clone().try_into().unwrap();
         assert_eq!(params2.population_fractions, fractions.0);
         assert_eq!(params2.contact_matrix, matrix.0);
+
+        let params3: DParameters = export.try_into().unwrap();
+        assert_eq!(params3.n(), 2);
+        assert_eq!(
+            params3.population_fractions.as_slice(),
+            fractions.0.as_slice()
+        );
+        assert_eq!(params3.contact_matrix.as_slice(), matrix.0.as_slice());
+    }
+
+    #[test]
+    fn test_try_from_wrong_group_count() {
+        let export = ParametersExport::default();
+        assert!(Parameters::<3>::try_from(export.clone()).is_err());
+        assert!(DParameters::try_from(export).is_ok());
+    }
+
+    #[test]
+    fn test_try_from_invalid_waning_rate() {
+        let mut export = ParametersExport {
+            recovered_waning_rate: 1.0 / 365.0,
+            ..Default::default()
+        };
+        assert!(Parameters::<2>::try_from(export.clone()).is_ok());
+        export.recovered_vaccinated_waning_rate = -0.01;
+        assert!(Parameters::<2>::try_from(export.clone()).is_err());
+        export.recovered_vaccinated_waning_rate = 0.0;
+        export.vaccine_waning_rate = f64::INFINITY;
+        assert!(Parameters::<2>::try_from(export).is_err());
+    }
+
+    #[test]
+    fn test_try_from_invalid_allocation() {
+        let mut export = ParametersExport::default();
+        export.mitigations.vaccine.allocation = VaccineAllocation::Priority(vec![1, 0]);
+        assert!(Parameters::<2>::try_from(export.clone()).is_ok());
+        export.mitigations.vaccine.allocation = VaccineAllocation::Priority(vec![1, 1]);
+        assert!(Parameters::<2>::try_from(export.clone()).is_err());
+        export.mitigations.vaccine.allocation = VaccineAllocation::Shares(vec![1.0]);
+        assert!(Parameters::<2>::try_from(export.clone()).is_err());
+        export.mitigations.vaccine.allocation = VaccineAllocation::ProRata;
+        export.mitigations.vaccine.coverage_caps = vec![0.9, 1.5];
+        assert!(Parameters::<2>::try_from(export).is_err());
+    }
+
+    #[test]
+    fn test_try_from_invalid_doses() {
+        let mut export = ParametersExport::default();
+        export.mitigations.vaccine.doses = 2;
+        assert!(Parameters::<2>::try_from(export.clone()).is_err());
+        export.mitigations.vaccine.partial_ve_s = vec![0.3];
+        export.mitigations.vaccine.partial_ve_i = vec![0.3];
+        export.mitigations.vaccine.partial_ve_p = vec![0.3];
+        assert!(Parameters::<2>::try_from(export.clone()).is_ok());
+        export.mitigations.vaccine.dose_interval = 0.0;
+        assert!(Parameters::<2>::try_from(export).is_err());
+    }
+
+    #[test]
+    fn test_try_from_invalid_length_of_stay() {
+        let mut export = ParametersExport {
+            icu_length_of_stay: vec![7.0],
+            ..Default::default()
+        };
+        assert!(Parameters::<2>::try_from(export.clone()).is_err());
+        export.icu_length_of_stay = vec![7.0, 0.0];
+        assert!(Parameters::<2>::try_from(export).is_err());
+    }
+
+    #[test]
+    fn test_try_from_invalid_icu_fractions() {
+        let mut export = ParametersExport {
+            fraction_icu: vec![0.0, 1.0],
+            fraction_dead_icu_overflow: vec![1.0, 0.0],
+            ..Default::default()
+        };
+        assert!(Parameters::<2>::try_from(export.clone()).is_ok());
+        export.fraction_icu = vec![0.2, 1.2];
+        assert!(Parameters::<2>::try_from(export.clone()).is_err());
+        export.fraction_icu = vec![0.2, 0.2];
+        export.fraction_dead_icu_overflow = vec![-0.1, 0.5];
+        assert!(Parameters::<2>::try_from(export).is_err());
+    }
+
+    #[test]
+    fn test_growth_rate() {
+        // With single-stage periods, R0 = (1 + r T_E)(1 + r T_I)
+        let params = Parameters {
+            r0: 2.0,
+            ..Default::default()
+        };
+        let r = params.growth_rate();
+        let r0 = (1.0 + r * params.latent_period) * (1.0 + r * params.infectious_period);
+        assert_float_eq!(r0, 2.0, abs <= 1e-9);
+        assert_float_eq!(params.r0_from_growth_rate(r).unwrap(), 2.0, abs <= 1e-9);
+
+        // Conversions are inverse of each other, including for declining
+        // epidemics and several stages
+        for r0 in [0.5, 1.0, 1.5, 3.0] {
+            let params = Parameters {
+                r0,
+                latent_stages: 2,
+                infectious_stages: 3,
+                seasonal_amplitude: 0.1,
+                ..Default::default()
+            };
+            let r = params.growth_rate();
+            assert_eq!(r > 0.0, r0 * 1.1 > 1.0);
+            assert_float_eq!(params.r0_from_growth_rate(r).unwrap(), r0, rmax <= 1e-9);
+        }
+
+        // Growth rates outside the domain of the conversion and doubling
+        // times that are not positive have no R0
+        let params = Parameters::default();
+        let min_growth_rate = -1.0 / params.latent_period.max(params.infectious_period);
+        for r in [
+            min_growth_rate,
+            -2.0,
+            f64::NEG_INFINITY,
+            f64::INFINITY,
+            f64::NAN,
+        ] {
+            assert!(params.r0_from_growth_rate(r).is_err());
+        }
+        assert!(params.r0_from_growth_rate(0.9 * min_growth_rate).unwrap() > 0.0);
+        for doubling_time in [0.0, -1.0, f64::INFINITY, f64::NAN] {
+            assert!(params.r0_from_doubling_time(doubling_time).is_err());
+        }
+
+        let export = ParametersExport {
+            r0: 2.0,
+            ..Default::default()
+        };
+        let doubling_time = get_doubling_time(export.clone()).unwrap();
+        assert_float_eq!(
+            r0_from_doubling_time(export.clone(), doubling_time).unwrap(),
+            2.0,
+            abs <= 1e-9
+        );
+        assert_eq!(
+            get_doubling_time(ParametersExport {
+                r0: 0.9,
+                ..export.clone()
+            })
+            .unwrap(),
+            f64::INFINITY
+        );
+
+        // R0 must be finite for the growth rate to be
+        for r0 in [f64::INFINITY, f64::NAN, -1.0] {
+            assert!(
+                DParameters::try_from(ParametersExport {
+                    r0,
+                    ..export.clone()
+                })
+                .is_err()
+            );
+        }
+        let huge = Parameters {
+            r0: f64::MAX,
+            ..Default::default()
+        };
+        assert!(huge.growth_rate().is_finite());
+    }
+
+    #[test]
+    fn test_transmission_multiplier() {
+        let point = |day, multiplier| RMultiplierPoint { day, multiplier };
+        let params = Parameters {
+            r_multiplier: vec![point(10.0, 1.0), point(20.0, 0.5), point(30.0, 2.0)],
+            ..Default::default()
+        };
+        assert_eq!(params.transmission_multiplier(0.0), 1.0);
+        assert_eq!(params.transmission_multiplier(15.0), 0.75);
+        assert_eq!(params.transmission_multiplier(25.0), 1.25);
+        assert_eq!(params.transmission_multiplier(100.0), 2.0);
+
+        let seasonal = Parameters {
+            seasonal_amplitude: 0.2,
+            seasonal_peak_day: 30.0,
+            ..Default::default()
+        };
+        assert_float_eq!(seasonal.transmission_multiplier(30.0), 1.2, abs <= 1e-12);
+        assert_float_eq!(seasonal.transmission_multiplier(212.5), 0.8, abs <= 1e-12);
+
+        let mut export = ParametersExport::from(params);
+        export.r_multiplier.swap(0, 1);
+        assert!(Parameters::<2>::try_from(export).is_err());
+    }
+
+    #[test]
+    fn test_importations() {
+        let period = |start, rate, group_shares| ImportationPeriod {
+            start,
+            rate,
+            group_shares,
+        };
+        let params = Parameters {
+            importations: vec![
+                period(10.0, 100.0, None),
+                period(20.0, 10.0, Some(vec![1.0, 3.0])),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(params.importations_at(5.0), vector![0.0, 0.0]);
+        assert_eq!(params.importations_at(10.0), vector![25.0, 75.0]);
+        assert_eq!(params.importations_at(30.0), vector![2.5, 7.5]);
+
+        let mut export = ParametersExport::from(params);
+        export.importations[1].group_shares = Some(vec![1.0]);
+        assert!(Parameters::<2>::try_from(export.clone()).is_err());
+        export.importations[1].group_shares = Some(vec![0.0, 0.0]);
+        assert!(Parameters::<2>::try_from(export.clone()).is_err());
+        export.importations[1].group_shares = None;
+        export.importations.swap(0, 1);
+        assert!(Parameters::<2>::try_from(export).is_err());
+    }
+
+    #[test]
+    fn test_initial_conditions() {
+        let params = Parameters {
+            initial_infection_shares: Some(vector![0.0, 2.0]),
+            ..Default::default()
+        };
+        assert_eq!(params.initial_infections_by_group(), vector![0.0, 1_000.0]);
+
+        let mut export = ParametersExport::from(params);
+        assert!(Parameters::<2>::try_from(export.clone()).is_ok());
+        export.initial_infection_shares = Some(vec![0.0, 0.0]);
+        assert!(Parameters::<2>::try_from(export.clone()).is_err());
+        export.initial_infection_shares = None;
+        export.initial_immunity = vec![0.5, 0.5];
+        export.initial_vaccinated = vec![0.6, 0.0];
+        assert!(Parameters::<2>::try_from(export.clone()).is_err());
+        export.initial_vaccinated = vec![0.4, 0.0];
+        assert!(Parameters::<2>::try_from(export.clone()).is_ok());
+        // Children are all immune or vaccinated, leaving nobody to infect
+        export.initial_vaccinated = vec![0.5, 0.0];
+        assert!(Parameters::<2>::try_from(export).is_err());
+    }
+
+    #[test]
+    fn test_variant() {
+        let variant = VariantParams {
+            relative_transmissibility: 1.5,
+            introduction_day: 30,
+            introduced_infections: 10.0,
+            vaccine_escape: 0.5,
+            infection_escape: 0.2,
+        };
+        assert_eq!(variant.efficacy((0.8, 0.4, 0.2)), (0.4, 0.2, 0.1));
+
+        let mut export = ParametersExport {
+            variant: Some(variant),
+            ..Default::default()
+        };
+        assert!(Parameters::<2>::try_from(export.clone()).is_ok());
+        export.variant = Some(VariantParams {
+            infection_escape: 1.5,
+            ..variant
+        });
+        assert!(Parameters::<2>::try_from(export).is_err());
+    }
+
+    #[test]
+    fn test_try_from_setting_contacts() {
+        let school = SettingContactsExport {
+            setting: ContactSetting::School,
+            contact_matrix: vec![12.0, 3.0, 1.0, 6.0],
+        };
+        let mut export = ParametersExport {
+            setting_contacts: vec![school.clone()],
+            ..Default::default()
+        };
+        let params = Parameters::<2>::try_from(export.clone()).unwrap();
+        assert_eq!(
+            params.total_contact_matrix(),
+            matrix![30.0, 4.0; 12.0, 18.0]
+        );
+        export.setting_contacts.push(school);
+        assert!(Parameters::<2>::try_from(export).is_err());
     }
 }